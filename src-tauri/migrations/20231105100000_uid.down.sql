@@ -0,0 +1 @@
+ALTER TABLE flashcard DROP COLUMN uid;
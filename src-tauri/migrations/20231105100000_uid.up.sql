@@ -0,0 +1 @@
+ALTER TABLE flashcard ADD COLUMN uid VARCHAR(64);
@@ -0,0 +1,8 @@
+-- Add up migration script here
+CREATE TABLE synced_file (
+    path TEXT PRIMARY KEY NOT NULL,
+    folder_path TEXT NOT NULL,
+    mtime INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    parser_version INTEGER NOT NULL
+);
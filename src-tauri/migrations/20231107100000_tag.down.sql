@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX flashcard_tag_tag_id;
+DROP TABLE flashcard_tag;
+DROP TABLE tag;
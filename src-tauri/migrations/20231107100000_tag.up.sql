@@ -0,0 +1,15 @@
+-- Add up migration script here
+CREATE TABLE tag (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE
+);
+
+CREATE TABLE flashcard_tag (
+    flashcard_id INTEGER NOT NULL,
+    tag_id INTEGER NOT NULL,
+    PRIMARY KEY (flashcard_id, tag_id),
+    FOREIGN KEY (flashcard_id) REFERENCES flashcard(id),
+    FOREIGN KEY (tag_id) REFERENCES tag(id)
+);
+
+CREATE INDEX flashcard_tag_tag_id ON flashcard_tag (tag_id);
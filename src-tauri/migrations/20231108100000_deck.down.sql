@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX flashcard_deck;
+ALTER TABLE flashcard DROP COLUMN deck;
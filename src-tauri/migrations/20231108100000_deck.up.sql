@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE flashcard ADD COLUMN deck VARCHAR(1024);
+
+CREATE INDEX flashcard_deck ON flashcard (deck);
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE flashcard DROP COLUMN note_line;
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE flashcard ADD COLUMN note_line INTEGER;
@@ -0,0 +1,324 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use anyhow::{bail, Result};
+use clap::{Args, Parser, Subcommand};
+
+use crate::config::Config;
+use crate::db::{CardFilter, Db};
+use crate::models::flashcard::DECK_SEPARATOR;
+use crate::models::prelude::Flashcard;
+use crate::parsers::diagnostic::{Diagnostic, Severity};
+use crate::parsers::parser;
+use crate::preview::{self, DiffLine, SyncPreview};
+use crate::review;
+use crate::sync;
+use crate::tui;
+
+/// A spaced repetition flashcard app. Launches the GUI when run
+/// without a subcommand.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Synchronize cards from the folder, or from all configured folders.
+    Sync {
+        folder: Option<String>,
+        /// Write card IDs into the markdown files, overrides the configured value.
+        #[arg(long)]
+        write_ids: bool,
+    },
+    /// Show what a sync of the folder would do, without changing anything.
+    Preview {
+        folder: String,
+        /// Also show the diffs of the updated cards.
+        #[arg(long)]
+        diff: bool,
+    },
+    /// Check the markdown files in the folder, or in all configured folders.
+    Lint { folder: Option<String> },
+    /// List the cards that are due for review.
+    Due {
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Review the due cards in the terminal.
+    Review {
+        /// Shuffle the cards, overrides the configured value.
+        #[arg(long)]
+        shuffle: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// List the tags and the number of their cards.
+    Tags,
+    /// List the decks with the numbers of their due and new cards.
+    Decks,
+    /// Show statistics about the cards and answers.
+    Stats,
+}
+
+/// Selects the cards to list or review.
+#[derive(Debug, Args)]
+pub struct FilterArgs {
+    /// Only the cards with the tag, can be given multiple times.
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+    /// Only the cards in the deck and its child decks, for example `lang::haskell`.
+    #[arg(long)]
+    deck: Option<String>,
+    /// Leave out the child decks of the deck.
+    #[arg(long, requires = "deck")]
+    no_subdecks: bool,
+}
+
+impl From<FilterArgs> for CardFilter {
+    fn from(args: FilterArgs) -> Self {
+        CardFilter {
+            tags: args.tags,
+            deck: args.deck,
+            subdecks: !args.no_subdecks,
+        }
+    }
+}
+
+/// Runs the command without the GUI.
+pub async fn run(command: Command, db: &Db, config: &Config) -> Result<()> {
+    match command {
+        Command::Sync { folder, write_ids } => {
+            let folders = match folder {
+                Some(folder) => vec![folder],
+                None => config.synced_folders.clone(),
+            };
+            if folders.is_empty() {
+                bail!("No folder given and no folders are configured to be synced");
+            }
+            for folder in folders {
+                let summary =
+                    sync::sync_folder(db, &folder, write_ids || config.write_card_ids).await?;
+                println!(
+                    "{}: {} new, {} updated, {} removed, {} files unchanged",
+                    folder,
+                    summary.added,
+                    summary.updated,
+                    summary.removed,
+                    summary.unchanged_files
+                );
+                for ambiguous in &summary.ambiguous {
+                    println!(
+                        "  ambiguous match: '{}', matched card {:?} out of {:?}",
+                        ambiguous.question.trim(),
+                        ambiguous.matched,
+                        ambiguous.candidates
+                    );
+                }
+                print_skipped(&summary.skipped_files);
+            }
+        }
+        Command::Preview { folder, diff } => {
+            let preview = preview::preview_sync(db, &folder).await?;
+            print_preview(&preview, diff);
+        }
+        Command::Lint { folder } => {
+            let folders = match folder {
+                Some(folder) => vec![folder],
+                None => config.synced_folders.clone(),
+            };
+            if folders.is_empty() {
+                bail!("No folder given and no folders are configured to be synced");
+            }
+            let mut diagnostics = vec![];
+            for folder in folders {
+                diagnostics.extend(parser::lint_folder(&folder).await);
+            }
+            for diagnostic in &diagnostics {
+                println!("{}", diagnostic);
+            }
+            let errors = diagnostics
+                .iter()
+                .filter(|d| d.severity == Severity::Error)
+                .count();
+            println!("{} errors, {} warnings", errors, diagnostics.len() - errors);
+            if errors > 0 {
+                bail!("Some cards are invalid");
+            }
+        }
+        Command::Due { filter } => {
+            let cards =
+                review::cards_to_review(db, &config.algorithm, false, &filter.into()).await?;
+            for card in &cards {
+                println!("{:>6}  {}", card.id.unwrap_or_default(), first_line(card));
+            }
+            println!("{} cards to review", cards.len());
+        }
+        Command::Review { shuffle, filter } => {
+            let shuffle = shuffle || config.random_shuffle;
+            let cards =
+                review::cards_to_review(db, &config.algorithm, shuffle, &filter.into()).await?;
+            if cards.is_empty() {
+                println!("Nothing to review");
+            } else if io::stdin().is_terminal() && io::stdout().is_terminal() {
+                let answered = tui::review(db, &cards).await?;
+                println!("Answered {} of {} cards", answered, cards.len());
+            } else {
+                review_loop(db, &cards).await?;
+            }
+        }
+        Command::Tags => {
+            for tag in db.get_tags().await? {
+                println!("{:>6}  #{}", tag.cards, tag.name);
+            }
+        }
+        Command::Decks => {
+            println!("{:>6} {:>6} {:>6}  deck", "cards", "due", "new");
+            for deck in review::decks(db, &config.algorithm).await? {
+                let depth = deck.name.matches(DECK_SEPARATOR).count();
+                let name = deck.name.rsplit(DECK_SEPARATOR).next().unwrap_or_default();
+                println!(
+                    "{:>6} {:>6} {:>6}  {}{}",
+                    deck.cards,
+                    deck.due,
+                    deck.new,
+                    "  ".repeat(depth),
+                    name
+                );
+            }
+        }
+        Command::Stats => {
+            let cards = db.get_cards(&CardFilter::default()).await?;
+            let answers = db.get_all_answers().await?;
+            let due = review::cards_to_review(db, &config.algorithm, false, &CardFilter::default())
+                .await?;
+            let today = chrono::Local::now().date_naive();
+            let answered_today = answers
+                .iter()
+                .filter(|a| a.timestamp.date() == today)
+                .count();
+            println!("Cards:          {}", cards.len());
+            println!("Due:            {}", due.len());
+            println!("Answers:        {}", answers.len());
+            println!("Answered today: {}", answered_today);
+            if !answers.is_empty() {
+                let average = answers.iter().map(|a| a.answer_rating as f64).sum::<f64>()
+                    / answers.len() as f64;
+                println!("Average rating: {:.1}", average);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn first_line(card: &Flashcard) -> &str {
+    card.question.lines().next().unwrap_or_default()
+}
+
+fn print_skipped(errors: &[Diagnostic]) {
+    for error in errors {
+        println!("  skipped {}", error);
+    }
+}
+
+fn print_diff(diff: &[DiffLine]) {
+    for line in diff {
+        match line {
+            DiffLine::Same(line) => println!("      {}", line),
+            DiffLine::Added(line) => println!("    + {}", line),
+            DiffLine::Removed(line) => println!("    - {}", line),
+        }
+    }
+}
+
+fn print_preview(preview: &SyncPreview, diff: bool) {
+    for card in &preview.new {
+        println!("new      {}", first_line(card));
+    }
+    for change in &preview.updated {
+        let id = change.old.id.unwrap_or_default();
+        match change.distance {
+            Some(distance) => println!(
+                "updated  {:>6}  {} (similar, distance {})",
+                id,
+                first_line(&change.new),
+                distance
+            ),
+            None => println!("updated  {:>6}  {}", id, first_line(&change.new)),
+        }
+        if diff {
+            println!("  question:");
+            print_diff(&change.question_diff);
+            println!("  answer:");
+            print_diff(&change.answer_diff);
+        }
+    }
+    for card in &preview.removed {
+        println!(
+            "removed  {:>6}  {}",
+            card.id.unwrap_or_default(),
+            first_line(card)
+        );
+    }
+    for ambiguous in &preview.ambiguous {
+        println!(
+            "ambiguous match: '{}', matched card {:?} out of {:?}",
+            ambiguous.question.trim(),
+            ambiguous.matched,
+            ambiguous.candidates
+        );
+    }
+    print_skipped(&preview.skipped_files);
+    println!(
+        "{} new, {} updated, {} removed, {} unchanged, {} files unchanged",
+        preview.new.len(),
+        preview.updated.len(),
+        preview.removed.len(),
+        preview.unchanged,
+        preview.unchanged_files
+    );
+}
+
+fn prompt(text: &str) -> Result<Option<String>> {
+    print!("{}", text);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+/// A plain line based review, reads the ratings from the standard input.
+/// Used when the review is not run in an interactive terminal.
+async fn review_loop(db: &Db, cards: &[Flashcard]) -> Result<()> {
+    for (i, card) in cards.iter().enumerate() {
+        println!("\n[{}/{}] {}", i + 1, cards.len(), card.question.trim_end());
+        if prompt("Press enter to show the answer...")?.is_none() {
+            return Ok(());
+        }
+        println!("{}", card.answer.trim_end());
+        loop {
+            let Some(input) = prompt("Rating 0-100 (s to skip, q to quit): ")? else {
+                return Ok(());
+            };
+            match input.as_str() {
+                "q" => return Ok(()),
+                "s" => break,
+                rating => match rating.parse::<i32>() {
+                    Ok(rating) if (0..=100).contains(&rating) => {
+                        let Some(id) = card.id else {
+                            bail!("The card is missing ID!");
+                        };
+                        review::answer_card(db, id, rating).await?;
+                        break;
+                    }
+                    _ => println!("The rating must be a number between 0 and 100"),
+                },
+            }
+        }
+    }
+    println!("\nAll cards reviewed");
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::repetition_algs::prelude::AlgorithmConfig;
+
+/// The application configuration. It is stored as JSON next to the
+/// database so that both the GUI and the command line use the same settings.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+    /// The algorithm to use for spaced repetition.
+    #[serde(default)]
+    pub algorithm: AlgorithmConfig,
+    /// The folders to synchronize.
+    #[serde(default)]
+    pub synced_folders: Vec<String>,
+    /// Synchronize on app start.
+    #[serde(default)]
+    pub sync_on_startup: bool,
+    /// Shuffle the cards before reviewing.
+    #[serde(default)]
+    pub random_shuffle: bool,
+    /// Write IDs of the cards into the synced markdown files.
+    #[serde(default)]
+    pub write_card_ids: bool,
+}
+
+impl Config {
+    /// Loads the config from the given file. If the file does not exist,
+    /// the default config is written to it and returned.
+    pub fn load(path: &Path) -> Result<Config> {
+        if !path.exists() {
+            debug!("Config {} does not exist, creating", path.display());
+            let config = Config::default();
+            config.save(path)?;
+            return Ok(config);
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config: {}", path.display()))?;
+        let config: Config = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse config: {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        self.validate()?;
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write config: {}", path.display()))
+    }
+
+    /// Checks the values that can be deserialized but make no sense.
+    pub fn validate(&self) -> Result<()> {
+        self.algorithm
+            .build()
+            .context("Invalid algorithm configuration")?;
+        for (i, folder) in self.synced_folders.iter().enumerate() {
+            if folder.trim().is_empty() {
+                bail!("Synced folder cannot be empty");
+            }
+            if self.synced_folders[..i].contains(folder) {
+                bail!("Folder {} is synced more than once", folder);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_config(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("flashcards-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn missing_config_is_created() {
+        let path = temp_config("missing.json");
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config, Config::default());
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = temp_config("roundtrip.json");
+        let config = Config {
+            algorithm: AlgorithmConfig::SuperMemo2 { cap: 30 },
+            synced_folders: vec!["/home/notes".to_string()],
+            sync_on_startup: true,
+            random_shuffle: true,
+            write_card_ids: true,
+        };
+        config.save(&path).unwrap();
+        assert_eq!(Config::load(&path).unwrap(), config);
+    }
+
+    #[test]
+    fn frontend_format() {
+        let json = r#"{
+            "algorithm": {
+                "name": "Naive Exponential",
+                "description": "Repeats questions every x days.",
+                "start": 2,
+                "cap": 64
+            },
+            "syncedFolders": ["/notes"],
+            "syncOnStartup": false,
+            "randomShuffle": true
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            config.algorithm,
+            AlgorithmConfig::NaiveExponential { start: 2, cap: 64 }
+        );
+        assert_eq!(config.synced_folders, vec!["/notes".to_string()]);
+        assert!(config.random_shuffle);
+    }
+
+    #[test]
+    fn invalid_config() {
+        let path = temp_config("invalid.json");
+        fs::write(&path, r#"{"syncedFolders": ["/a", "/a"]}"#).unwrap();
+        assert!(Config::load(&path).is_err());
+        fs::write(&path, "not a json").unwrap();
+        assert!(Config::load(&path).is_err());
+    }
+}
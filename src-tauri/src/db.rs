@@ -1,8 +1,99 @@
-use anyhow::Result;
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
 use sqlx::prelude::*;
-use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::sqlite::{Sqlite, SqliteConnection, SqlitePool, SqliteRow};
+use sqlx::QueryBuilder;
+
+use crate::models::flashcard::DECK_SEPARATOR;
+use crate::models::prelude::{Answer, Flashcard, SyncedFile, Tag};
+
+fn card_from_row(row: SqliteRow) -> Flashcard {
+    Flashcard {
+        id: Some(row.get("id")),
+        question: row.get("question"),
+        answer: row.get("answer"),
+        folder: row.get("folder_path"),
+        path: row.get("file_path"),
+        uid: row.get("uid"),
+        tags: vec![],
+        deck: row.get("deck"),
+        note_line: row.get("note_line"),
+        algorithm: row.get("algorithm"),
+    }
+}
 
-use crate::models::prelude::{Answer, Flashcard};
+/// Selects the cards returned by [`Db::get_cards`].
+#[derive(Debug, Clone, Default)]
+pub struct CardFilter {
+    /// Only the cards with at least one of the tags, compared regardless
+    /// of case.
+    pub tags: Vec<String>,
+    /// Only the cards in the deck.
+    pub deck: Option<String>,
+    /// Also the cards in the child decks of the deck.
+    pub subdecks: bool,
+}
+
+/// Replaces the tags of the card, unknown tags are created.
+/// Replaces the tags of the cards, given with their IDs. New tags are
+/// created.
+async fn set_cards_tags(conn: &mut SqliteConnection, cards: &[(i32, &Flashcard)]) -> Result<()> {
+    for cards in cards.chunks(BATCH_SIZE) {
+        let mut query =
+            QueryBuilder::<Sqlite>::new("DELETE FROM flashcard_tag WHERE flashcard_id IN (");
+        let mut ids = query.separated(", ");
+        for (id, _) in cards {
+            ids.push_bind(*id);
+        }
+        query.push(")");
+        query.build().execute(&mut *conn).await?;
+    }
+
+    let tags: Vec<(i32, &str)> = cards
+        .iter()
+        .flat_map(|(id, card)| card.tags.iter().map(|tag| (*id, tag.as_str())))
+        .collect();
+    for tags in tags.chunks(BATCH_SIZE) {
+        let mut query = QueryBuilder::<Sqlite>::new("INSERT INTO tag (name) ");
+        query.push_values(tags, |mut row, (_, tag)| {
+            row.push_bind(*tag);
+        });
+        query.push(" ON CONFLICT (name) DO NOTHING");
+        query.build().execute(&mut *conn).await?;
+
+        let mut query = QueryBuilder::<Sqlite>::new("WITH new (flashcard_id, name) AS (");
+        query.push_values(tags, |mut row, (id, tag)| {
+            row.push_bind(*id).push_bind(*tag);
+        });
+        query.push(
+            "
+)
+INSERT OR IGNORE INTO flashcard_tag (flashcard_id, tag_id)
+SELECT new.flashcard_id, tag.id FROM new
+JOIN tag ON tag.name = new.name
+            ",
+        );
+        query.build().execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+/// Number of cards inserted, updated or archived by a single query, keeps the number
+/// of bound parameters under the SQLite limit.
+const BATCH_SIZE: usize = 500;
+
+/// Changes of the database made by a folder sync.
+#[derive(Debug, Default)]
+pub struct SyncChanges {
+    pub added: Vec<Flashcard>,
+    pub updated: Vec<Flashcard>,
+    pub archived: Vec<Flashcard>,
+    /// Files which were read during the sync.
+    pub files: Vec<SyncedFile>,
+    /// Paths of the synced files which no longer exist.
+    pub removed_files: Vec<String>,
+}
 
 pub struct Db {
     pool: SqlitePool,
@@ -29,72 +120,122 @@ VALUES (?, ?, ?)
         .map_err(|e| e.into())
     }
 
-    pub async fn add_card(&self, card: Flashcard) -> Result<()> {
-        sqlx::query(
+    pub async fn get_card(&self, id: i32) -> Result<Flashcard> {
+        let mut card = sqlx::query("SELECT * FROM flashcard WHERE id = ?")
+            .bind(id)
+            .map(card_from_row)
+            .fetch_one(&self.pool)
+            .await?;
+        card.tags = sqlx::query_scalar(
             "
-INSERT INTO flashcard (question, answer, folder_path, file_path)
-VALUES (?, ?, ?, ?)
+SELECT tag.name FROM flashcard_tag
+JOIN tag ON tag.id = flashcard_tag.tag_id
+WHERE flashcard_tag.flashcard_id = ?
+ORDER BY flashcard_tag.rowid
             ",
         )
-        .bind(card.question)
-        .bind(card.answer)
-        .bind(card.folder)
-        .bind(card.path)
-        .execute(&self.pool)
-        .await
-        .map(|_| ())
-        .map_err(|e| e.into())
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(card)
     }
 
-    pub async fn update_card(&self, card: &Flashcard) -> Result<()> {
-        match card.id {
-            None => Err(anyhow::anyhow!("The card is missing ID!")),
-            Some(_) => sqlx::query(
-                "
-UPDATE flashcard
-SET question = ?, answer = ?
-WHERE id = ?
+    /// Fills in the tags of the cards, in the order they were written.
+    async fn load_tags(&self, cards: &mut [Flashcard]) -> Result<()> {
+        let rows: Vec<(i32, String)> = sqlx::query_as(
+            "
+SELECT flashcard_tag.flashcard_id, tag.name FROM flashcard_tag
+JOIN tag ON tag.id = flashcard_tag.tag_id
+ORDER BY flashcard_tag.rowid
             ",
-            )
-            .bind(card.question.as_str())
-            .bind(card.answer.as_str())
-            .bind(card.id)
-            .execute(&self.pool)
-            .await
-            .map(|_| ())
-            .map_err(|e| e.into()),
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut tags: HashMap<i32, Vec<String>> = HashMap::new();
+        for (id, name) in rows {
+            tags.entry(id).or_default().push(name);
+        }
+        for card in cards {
+            if let Some(card_tags) = card.id.and_then(|id| tags.remove(&id)) {
+                card.tags = card_tags;
+            }
         }
+        Ok(())
     }
 
-    pub async fn get_card(&self, id: i32) -> Result<Flashcard> {
-        sqlx::query("SELECT * FROM flashcard WHERE id = ?")
-            .bind(id)
-            .map(|row: SqliteRow| Flashcard {
-                id: Some(row.get(0)),
-                question: row.get(1),
-                answer: row.get(2),
-                folder: row.get(3),
-                path: row.get(4),
-            })
-            .fetch_one(&self.pool)
-            .await
-            .map_err(|e| e.into())
-    }
-
-    /// Pulls the cards from the database. Caches the result, pulls only once
-    /// for multiple calls.
-    pub async fn get_cards(&self) -> Result<Vec<Flashcard>> {
-        sqlx::query("SELECT * FROM flashcard")
-            .map(|row: SqliteRow| Flashcard {
-                id: Some(row.get(0)),
-                question: row.get(1),
-                answer: row.get(2),
-                folder: row.get(3),
-                path: row.get(4),
-            })
+    /// Pulls the cards which are not archived and match the filter from the
+    /// database.
+    pub async fn get_cards(&self, filter: &CardFilter) -> Result<Vec<Flashcard>> {
+        let mut query =
+            QueryBuilder::<Sqlite>::new("SELECT * FROM flashcard WHERE archived = FALSE");
+        if !filter.tags.is_empty() {
+            query.push(
+                "
+AND id IN (
+    SELECT flashcard_tag.flashcard_id FROM flashcard_tag
+    JOIN tag ON tag.id = flashcard_tag.tag_id
+    WHERE tag.name IN (",
+            );
+            let mut names = query.separated(", ");
+            for tag in &filter.tags {
+                names.push_bind(tag.as_str());
+            }
+            query.push("))");
+        }
+        if let Some(deck) = &filter.deck {
+            query.push(" AND (deck = ").push_bind(deck.as_str());
+            if filter.subdecks {
+                let prefix = format!("{}{}", deck, DECK_SEPARATOR);
+                query
+                    .push(" OR substr(deck, 1, ")
+                    .push_bind(prefix.chars().count() as i64)
+                    .push(") = ")
+                    .push_bind(prefix);
+            }
+            query.push(")");
+        }
+        let mut cards = query
+            .build()
+            .map(card_from_row)
             .fetch_all(&self.pool)
-            .await
-            .map_err(|e| e.into())
+            .await?;
+        self.load_tags(&mut cards).await?;
+        Ok(cards)
+    }
+
+    /// Pulls the cards which were synced from the folder, either the archived
+    /// ones or the ones which are not archived.
+    pub async fn get_folder_cards(&self, folder: &str, archived: bool) -> Result<Vec<Flashcard>> {
+        let mut cards =
+            sqlx::query("SELECT * FROM flashcard WHERE archived = ? AND folder_path = ?")
+                .bind(archived)
+                .bind(folder)
+                .map(card_from_row)
+                .fetch_all(&self.pool)
+                .await?;
+        self.load_tags(&mut cards).await?;
+        Ok(cards)
+    }
+
+    /// Returns the tags of the cards which are not archived, sorted by name.
+    pub async fn get_tags(&self) -> Result<Vec<Tag>> {
+        sqlx::query(
+            "
+SELECT tag.name, COUNT(*) FROM tag
+JOIN flashcard_tag ON flashcard_tag.tag_id = tag.id
+JOIN flashcard ON flashcard.id = flashcard_tag.flashcard_id
+WHERE flashcard.archived = FALSE
+GROUP BY tag.id
+ORDER BY tag.name
+            ",
+        )
+        .map(|row: SqliteRow| Tag {
+            name: row.get(0),
+            cards: row.get(1),
+        })
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
     }
 
     pub async fn get_answers(&self, card: &Flashcard) -> Result<Vec<Answer>> {
@@ -115,4 +256,382 @@ WHERE flashcard_id = ?
         .await
         .map_err(|e| e.into())
     }
+
+    /// Returns answers to all flashcards, sorted by flashcard and timestamp.
+    pub async fn get_all_answers(&self) -> Result<Vec<Answer>> {
+        sqlx::query(
+            "
+SELECT * FROM answer
+ORDER BY flashcard_id, timestamp
+            ",
+        )
+        .map(|row: SqliteRow| Answer {
+            id: Some(row.get(0)),
+            flashcard_id: row.get(1),
+            timestamp: row.get(2),
+            answer_rating: row.get(3),
+        })
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    /// Returns the files synced from the folder.
+    pub async fn get_synced_files(&self, folder: &str) -> Result<Vec<SyncedFile>> {
+        sqlx::query(
+            "SELECT path, folder_path, mtime, hash, parser_version FROM synced_file WHERE folder_path = ?",
+        )
+        .bind(folder)
+        .map(|row: SqliteRow| SyncedFile {
+            path: row.get(0),
+            folder: row.get(1),
+            mtime: row.get(2),
+            hash: row.get(3),
+            parser_version: row.get(4),
+        })
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.into())
+    }
+
+    /// Applies all changes of a folder sync in a single transaction. If any
+    /// of them fails, none are applied.
+    pub async fn apply_sync(&self, changes: &SyncChanges) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let mut tagged = vec![];
+        for cards in changes.added.chunks(BATCH_SIZE) {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                "INSERT INTO flashcard (question, answer, folder_path, file_path, uid, deck, note_line, algorithm) ",
+            );
+            query.push_values(cards, |mut row, card| {
+                row.push_bind(card.question.as_str())
+                    .push_bind(card.answer.as_str())
+                    .push_bind(card.folder.as_deref())
+                    .push_bind(card.path.as_deref())
+                    .push_bind(card.uid.as_deref())
+                    .push_bind(card.deck.as_deref())
+                    .push_bind(card.note_line)
+                    .push_bind(card.algorithm.as_deref());
+            });
+            query.push(" RETURNING id");
+            let mut ids: Vec<i32> = query
+                .build()
+                .map(|row: SqliteRow| row.get(0))
+                .fetch_all(&mut *tx)
+                .await?;
+            if ids.len() != cards.len() {
+                bail!("Inserted {} cards instead of {}", ids.len(), cards.len());
+            }
+            // The returned rows are in no particular order, but the rows are
+            // inserted in order with increasing IDs.
+            ids.sort_unstable();
+            tagged.extend(
+                ids.into_iter()
+                    .zip(cards)
+                    .filter(|(_, card)| !card.tags.is_empty()),
+            );
+        }
+        set_cards_tags(&mut tx, &tagged).await?;
+
+        let mut updated = vec![];
+        for card in &changes.updated {
+            let Some(id) = card.id else {
+                bail!("The card is missing ID!");
+            };
+            updated.push((id, card));
+        }
+        for cards in updated.chunks(BATCH_SIZE) {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                "WITH new (id, question, answer, file_path, uid, deck, note_line, algorithm) AS (",
+            );
+            query.push_values(cards, |mut row, (id, card)| {
+                row.push_bind(*id)
+                    .push_bind(card.question.as_str())
+                    .push_bind(card.answer.as_str())
+                    .push_bind(card.path.as_deref())
+                    .push_bind(card.uid.as_deref())
+                    .push_bind(card.deck.as_deref())
+                    .push_bind(card.note_line)
+                    .push_bind(card.algorithm.as_deref());
+            });
+            // Matched archived cards are restored.
+            query.push(
+                "
+)
+UPDATE flashcard
+SET question = new.question, answer = new.answer, file_path = new.file_path, uid = new.uid,
+    deck = new.deck, note_line = new.note_line, algorithm = new.algorithm, archived = FALSE
+FROM new
+WHERE flashcard.id = new.id
+                ",
+            );
+            query.build().execute(&mut *tx).await?;
+        }
+        set_cards_tags(&mut tx, &updated).await?;
+
+        // Archived cards are soft deleted, they are kept together with their
+        // answers but are no longer returned by `get_cards`.
+        for cards in changes.archived.chunks(BATCH_SIZE) {
+            let mut query =
+                QueryBuilder::<Sqlite>::new("UPDATE flashcard SET archived = TRUE WHERE id IN (");
+            let mut ids = query.separated(", ");
+            for card in cards {
+                let Some(id) = card.id else {
+                    bail!("The card is missing ID!");
+                };
+                ids.push_bind(id);
+            }
+            query.push(")");
+            query.build().execute(&mut *tx).await?;
+        }
+
+        for path in &changes.removed_files {
+            sqlx::query("DELETE FROM synced_file WHERE path = ?")
+                .bind(path.as_str())
+                .execute(&mut *tx)
+                .await?;
+        }
+        for file in &changes.files {
+            sqlx::query(
+                "
+INSERT INTO synced_file (path, folder_path, mtime, hash, parser_version)
+VALUES (?, ?, ?, ?, ?)
+ON CONFLICT (path) DO UPDATE
+SET folder_path = excluded.folder_path, mtime = excluded.mtime, hash = excluded.hash,
+    parser_version = excluded.parser_version
+            ",
+            )
+            .bind(file.path.as_str())
+            .bind(file.folder.as_str())
+            .bind(file.mtime)
+            .bind(file.hash.as_str())
+            .bind(file.parser_version)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// An empty database in memory, for tests.
+#[cfg(test)]
+pub async fn memory_db() -> Db {
+    // Every connection to an in-memory database has its own database.
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!().run(&pool).await.unwrap();
+    Db::new(pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::parser::PARSER_VERSION;
+
+    fn card(question: &str) -> Flashcard {
+        Flashcard {
+            question: question.to_string(),
+            answer: "answer".to_string(),
+            folder: Some("/notes".to_string()),
+            path: Some("/notes/a.md".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn apply_sync() {
+        let db = memory_db().await;
+        let added: Vec<Flashcard> = (0..BATCH_SIZE + 10)
+            .map(|i| {
+                let question = format!("q{}", i);
+                tagged(&question, &[&question])
+            })
+            .collect();
+        let changes = SyncChanges {
+            added,
+            files: vec![SyncedFile {
+                path: "/notes/a.md".to_string(),
+                folder: "/notes".to_string(),
+                mtime: 1,
+                hash: "h".to_string(),
+                parser_version: PARSER_VERSION,
+            }],
+            ..Default::default()
+        };
+        db.apply_sync(&changes).await.unwrap();
+        let cards = db.get_folder_cards("/notes", false).await.unwrap();
+        assert_eq!(cards.len(), BATCH_SIZE + 10);
+        assert!(cards
+            .iter()
+            .all(|card| card.tags == vec![card.question.clone()]));
+
+        let changes = SyncChanges {
+            updated: vec![Flashcard {
+                question: "updated".to_string(),
+                ..cards[0].clone()
+            }],
+            archived: cards[1..].to_vec(),
+            removed_files: vec!["/notes/a.md".to_string()],
+            ..Default::default()
+        };
+        db.apply_sync(&changes).await.unwrap();
+        let cards = db.get_folder_cards("/notes", false).await.unwrap();
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].question, "updated");
+        assert!(db.get_synced_files("/notes").await.unwrap().is_empty());
+
+        // Updating an archived card restores it.
+        let archived = db.get_folder_cards("/notes", true).await.unwrap();
+        assert_eq!(archived.len(), BATCH_SIZE + 9);
+        let changes = SyncChanges {
+            updated: vec![archived[0].clone()],
+            ..Default::default()
+        };
+        db.apply_sync(&changes).await.unwrap();
+        let cards = db.get_folder_cards("/notes", false).await.unwrap();
+        assert_eq!(cards.len(), 2);
+
+        let archived = db.get_folder_cards("/notes", true).await.unwrap();
+        let changes = SyncChanges {
+            updated: archived
+                .into_iter()
+                .map(|card| Flashcard {
+                    answer: "restored".to_string(),
+                    tags: vec!["tag".to_string()],
+                    ..card
+                })
+                .collect(),
+            ..Default::default()
+        };
+        db.apply_sync(&changes).await.unwrap();
+        let cards = db.get_folder_cards("/notes", false).await.unwrap();
+        assert_eq!(cards.len(), BATCH_SIZE + 10);
+        let restored: Vec<&Flashcard> = cards
+            .iter()
+            .filter(|card| card.answer == "restored")
+            .collect();
+        assert_eq!(restored.len(), BATCH_SIZE + 8);
+        assert!(restored.iter().all(|card| card.tags == vec!["tag"]));
+    }
+
+    fn tagged(question: &str, tags: &[&str]) -> Flashcard {
+        Flashcard {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..card(question)
+        }
+    }
+
+    #[tokio::test]
+    async fn tags() {
+        let db = memory_db().await;
+        let changes = SyncChanges {
+            added: vec![
+                tagged("q1", &["haskell", "lang"]),
+                tagged("q2", &["Lang"]),
+                tagged("q3", &[]),
+            ],
+            ..Default::default()
+        };
+        db.apply_sync(&changes).await.unwrap();
+        let cards = db.get_cards(&CardFilter::default()).await.unwrap();
+        assert_eq!(cards.len(), 3);
+        assert_eq!(cards[0].tags, vec!["haskell", "lang"]);
+        // Tags differing only in case are the same tag.
+        assert_eq!(cards[1].tags, vec!["lang"]);
+
+        let haskell = db
+            .get_cards(&CardFilter {
+                tags: vec!["Haskell".to_string()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(haskell.len(), 1);
+        assert_eq!(haskell[0].question, "q1");
+        let any = db
+            .get_cards(&CardFilter {
+                tags: vec!["haskell".to_string(), "lang".to_string()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(any.len(), 2);
+
+        let changes = SyncChanges {
+            updated: vec![Flashcard {
+                id: cards[0].id,
+                ..tagged("q1", &["rust"])
+            }],
+            archived: vec![cards[1].clone()],
+            ..Default::default()
+        };
+        db.apply_sync(&changes).await.unwrap();
+        let card = db.get_card(cards[0].id.unwrap()).await.unwrap();
+        assert_eq!(card.tags, vec!["rust"]);
+        assert_eq!(
+            db.get_tags().await.unwrap(),
+            vec![Tag {
+                name: "rust".to_string(),
+                cards: 1
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn decks() {
+        let db = memory_db().await;
+        let in_deck = |question: &str, deck: &str| Flashcard {
+            deck: Some(deck.to_string()),
+            ..card(question)
+        };
+        let changes = SyncChanges {
+            added: vec![
+                in_deck("q1", "lang"),
+                in_deck("q2", "lang::haskell"),
+                in_deck("q3", "language"),
+                in_deck("q4", "lang::haskell::monads"),
+            ],
+            ..Default::default()
+        };
+        db.apply_sync(&changes).await.unwrap();
+        let questions = |cards: Vec<Flashcard>| -> Vec<String> {
+            cards.into_iter().map(|card| card.question).collect()
+        };
+        let filter = CardFilter {
+            deck: Some("lang".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(questions(db.get_cards(&filter).await.unwrap()), vec!["q1"]);
+        let filter = CardFilter {
+            subdecks: true,
+            ..filter
+        };
+        assert_eq!(
+            questions(db.get_cards(&filter).await.unwrap()),
+            vec!["q1", "q2", "q4"]
+        );
+    }
+
+    #[tokio::test]
+    async fn rollback_on_failure() {
+        let db = memory_db().await;
+        let changes = SyncChanges {
+            added: vec![card("q1"), card("q2")],
+            // The card was never saved, so the update fails.
+            updated: vec![card("q3")],
+            ..Default::default()
+        };
+        assert!(db.apply_sync(&changes).await.is_err());
+        assert!(db
+            .get_cards(&CardFilter::default())
+            .await
+            .unwrap()
+            .is_empty());
+    }
 }
@@ -3,27 +3,53 @@
 
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
 
+use clap::Parser;
+use models::deck::Deck;
 use models::flashcard::Flashcard;
-use rand::seq::SliceRandom;
+use models::tag::Tag;
 use sqlx::migrate::MigrateDatabase;
 use sqlx::sqlite::SqlitePoolOptions;
 use sqlx::SqlitePool;
 use std::env;
 use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::sync::{Mutex, RwLock};
 
+mod cli;
+mod config;
 mod db;
 mod models;
 mod parsers;
+mod preview;
 mod repetition_algs;
+mod review;
 mod sync;
+mod tui;
+mod watcher;
 
-use crate::models::answer::Answer;
-use crate::repetition_algs::prelude::*;
-use db::Db;
-use log::debug;
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::parsers::diagnostic::Diagnostic;
+use crate::preview::SyncPreview;
+use crate::sync::SyncSummary;
+use crate::watcher::{FolderWatcher, SyncEvent, SYNC_EVENT};
+use db::{CardFilter, Db};
+use log::{debug, warn};
+use tauri::Manager;
+use tokio::sync::mpsc::UnboundedReceiver;
 
 struct AppState {
     db: Db,
+    config: RwLock<Config>,
+    config_path: PathBuf,
+    /// Why the config could not be loaded, the default one is used instead.
+    config_error: Option<String>,
+    /// The config file did not exist and was created on this start.
+    config_created: bool,
+    watcher: FolderWatcher,
+    /// Held during a folder sync, so that the watcher and the frontend never
+    /// sync at the same time.
+    sync_lock: Mutex<()>,
 }
 
 pub fn chained_errs_to_string(err: anyhow::Error) -> String {
@@ -33,7 +59,8 @@ pub fn chained_errs_to_string(err: anyhow::Error) -> String {
         .join("; ")
 }
 
-pub async fn estabilish_connection() -> sqlx::Result<SqlitePool> {
+/// The folder where the database and the config are stored.
+pub fn app_dir() -> PathBuf {
     // TODO: Windows. We would like to use the tauri getAppDataDir or whatever,
     // however, this requires app config. That would require that this functions
     // runs after the app is set up. We could make it a command and invoke it from
@@ -44,7 +71,11 @@ pub async fn estabilish_connection() -> sqlx::Result<SqlitePool> {
     let app_path = env::var("DATABASE_URL").unwrap_or(format!("{}/.flashcards", home));
     // TODO: We deliberately ignore this since the folder will often exists
     let _ = fs::create_dir_all(&app_path);
-    let database_url = format!("{}/flashcards.db", app_path);
+    PathBuf::from(app_path)
+}
+
+pub async fn estabilish_connection(app_dir: &Path) -> sqlx::Result<SqlitePool> {
+    let database_url = format!("{}/flashcards.db", app_dir.display());
     debug!("Opening database at {}", database_url);
 
     if !sqlx::Sqlite::database_exists(&database_url).await? {
@@ -60,9 +91,37 @@ pub async fn estabilish_connection() -> sqlx::Result<SqlitePool> {
     Ok(pool)
 }
 
+/// Builds the filter of the cards from the optional command arguments.
+/// The child decks are included unless disabled.
+fn card_filter(
+    tags: Option<Vec<String>>,
+    deck: Option<String>,
+    subdecks: Option<bool>,
+) -> CardFilter {
+    CardFilter {
+        tags: tags.unwrap_or_default(),
+        deck,
+        subdecks: subdecks.unwrap_or(true),
+    }
+}
+
 #[tauri::command]
-async fn get_all_cards(state: tauri::State<'_, AppState>) -> Result<Vec<Flashcard>, String> {
-    state.db.get_cards().await.map_err(chained_errs_to_string)
+async fn get_all_cards(
+    tags: Option<Vec<String>>,
+    deck: Option<String>,
+    subdecks: Option<bool>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<Flashcard>, String> {
+    state
+        .db
+        .get_cards(&card_filter(tags, deck, subdecks))
+        .await
+        .map_err(chained_errs_to_string)
+}
+
+#[tauri::command]
+async fn get_tags(state: tauri::State<'_, AppState>) -> Result<Vec<Tag>, String> {
+    state.db.get_tags().await.map_err(chained_errs_to_string)
 }
 
 #[tauri::command]
@@ -73,26 +132,24 @@ async fn get_card(id: i32, state: tauri::State<'_, AppState>) -> Result<Flashcar
 #[tauri::command]
 async fn get_cards_to_review(
     shuffle: bool,
+    tags: Option<Vec<String>>,
+    deck: Option<String>,
+    subdecks: Option<bool>,
     state: tauri::State<'_, AppState>,
 ) -> Result<Vec<Flashcard>, String> {
-    let ra = NaiveExponentialRA::new(50, 128);
-    let cards = state.db.get_cards().await.map_err(chained_errs_to_string)?;
-    let mut result = vec![];
-    // We would like filter but async closures and such...
-    for card in cards {
-        let answers = state
-            .db
-            .get_answers(&card)
-            .await
-            .map_err(chained_errs_to_string)?;
-        if ra.repeat_question(&answers) {
-            result.push(card);
-        }
-    }
-    if shuffle {
-        result.shuffle(&mut rand::thread_rng());
-    }
-    Ok(result)
+    let algorithm = state.config.read().await.algorithm.clone();
+    let filter = card_filter(tags, deck, subdecks);
+    review::cards_to_review(&state.db, &algorithm, shuffle, &filter)
+        .await
+        .map_err(chained_errs_to_string)
+}
+
+#[tauri::command]
+async fn get_decks(state: tauri::State<'_, AppState>) -> Result<Vec<Deck>, String> {
+    let algorithm = state.config.read().await.algorithm.clone();
+    review::decks(&state.db, &algorithm)
+        .await
+        .map_err(chained_errs_to_string)
 }
 
 #[tauri::command]
@@ -101,72 +158,226 @@ async fn answer_question(
     answer_rating: i32,
     state: tauri::State<'_, AppState>,
 ) -> Result<(), String> {
-    println!(
+    debug!(
         "Answering question {} with rating {}",
         flashcard_id, answer_rating
     );
-    if !(0..=100).contains(&answer_rating) {
-        return Err("Answer rating must be between 0 and 100".to_string());
-    }
-    let answer = Answer {
-        id: None,
-        flashcard_id,
-        timestamp: chrono::Local::now().naive_local(),
-        answer_rating,
-    };
-    let x = state
-        .db
-        .persist_answer(answer)
+    review::answer_card(&state.db, flashcard_id, answer_rating)
         .await
-        .map_err(chained_errs_to_string);
-    println!("Persisting answer");
-    if x.is_err() {
-        println!("Error: {}", x.unwrap_err());
-    }
+        .map_err(chained_errs_to_string)
+}
+
+#[tauri::command]
+async fn sync_flashcards(
+    folder: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<SyncSummary, String> {
+    let _guard = state.sync_lock.lock().await;
+    let write_ids = state.config.read().await.write_card_ids;
+    sync::sync_folder(&state.db, &folder, write_ids)
+        .await
+        .map_err(chained_errs_to_string)
+}
+
+/// Shows what a sync of the folder would do, without changing anything.
+#[tauri::command]
+async fn preview_sync(
+    folder: String,
+    state: tauri::State<'_, AppState>,
+) -> Result<SyncPreview, String> {
+    preview::preview_sync(&state.db, &folder)
+        .await
+        .map_err(chained_errs_to_string)
+}
+
+/// Checks the markdown files in the folder for invalid cards.
+#[tauri::command]
+async fn lint_folder(folder: String) -> Vec<Diagnostic> {
+    parsers::parser::lint_folder(&folder).await
+}
+
+#[tauri::command]
+async fn get_config(state: tauri::State<'_, AppState>) -> Result<Config, String> {
+    Ok(state.config.read().await.clone())
+}
+
+#[tauri::command]
+async fn get_config_error(state: tauri::State<'_, AppState>) -> Result<Option<String>, String> {
+    Ok(state.config_error.clone())
+}
+
+#[tauri::command]
+async fn set_config(config: Config, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    config
+        .save(&state.config_path)
+        .map_err(chained_errs_to_string)?;
+    state
+        .watcher
+        .watch(&config.synced_folders)
+        .map_err(chained_errs_to_string)?;
+    *state.config.write().await = config;
     Ok(())
 }
 
+/// Saves the config which older versions kept in the frontend, if the
+/// config file was only created on this start. Returns whether it was saved.
+#[tauri::command]
+async fn migrate_config(config: Config, state: tauri::State<'_, AppState>) -> Result<bool, String> {
+    if !state.config_created || *state.config.read().await != Config::default() {
+        return Ok(false);
+    }
+    set_config(config, state).await?;
+    Ok(true)
+}
+
+/// Fits the FSRS weights to all answers in the database and saves them
+/// into the config, so that the following reviews use them.
 #[tauri::command]
-async fn sync_flashcards(folder: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let new_cards = parsers::parser::parse_folder(&folder)
+async fn optimize_fsrs(state: tauri::State<'_, AppState>) -> Result<Vec<f64>, String> {
+    let answers = state
+        .db
+        .get_all_answers()
         .await
         .map_err(chained_errs_to_string)?;
-    let old_cards = state.db.get_cards().await.map_err(|e| e.to_string())?;
-    let synced_cards = sync::sync(&old_cards, new_cards).await;
-    for card in synced_cards {
-        match card {
-            sync::CardType::New(card) => {
-                state.db.add_card(card).await.map_err(|e| e.to_string())?;
-            }
-            sync::CardType::Old(card) => {
-                state
-                    .db
-                    .update_card(&card)
-                    .await
-                    .map_err(|e| e.to_string())?;
+    let histories = repetition_algs::fsrs::optimizer::group_by_card(answers);
+    let weights =
+        tokio::task::spawn_blocking(move || repetition_algs::fsrs::optimizer::optimize(&histories))
+            .await
+            .map_err(|e| e.to_string())?;
+    let mut config = state.config.write().await;
+    let new_config = Config {
+        algorithm: config
+            .algorithm
+            .with_fsrs_weights(weights)
+            .map_err(chained_errs_to_string)?,
+        ..config.clone()
+    };
+    new_config
+        .save(&state.config_path)
+        .map_err(chained_errs_to_string)?;
+    *config = new_config;
+    Ok(weights.to_vec())
+}
+
+/// Syncs the folder and notifies the frontend about the result.
+async fn sync_and_notify(app: &tauri::AppHandle, folder: String) {
+    let state = app.state::<AppState>();
+    let guard = state.sync_lock.lock().await;
+    let write_ids = state.config.read().await.write_card_ids;
+    let result = sync::sync_folder(&state.db, &folder, write_ids).await;
+    drop(guard);
+    let event = match result {
+        Ok(summary) => SyncEvent {
+            folder,
+            summary: Some(summary),
+            error: None,
+        },
+        Err(e) => SyncEvent {
+            folder,
+            summary: None,
+            error: Some(chained_errs_to_string(e)),
+        },
+    };
+    if let Some(error) = &event.error {
+        warn!("Failed to sync {}: {}", event.folder, error);
+    }
+    if let Err(e) = app.emit_all(SYNC_EVENT, event) {
+        warn!("Failed to emit the sync event: {}", e);
+    }
+}
+
+/// Syncs the folders on startup and then every folder reported by the
+/// watcher. Changes reported during a sync are synced only once afterwards.
+async fn sync_changes(
+    app: tauri::AppHandle,
+    startup: Vec<String>,
+    mut changes: UnboundedReceiver<String>,
+) {
+    for folder in startup {
+        sync_and_notify(&app, folder).await;
+    }
+    while let Some(folder) = changes.recv().await {
+        let mut folders = vec![folder];
+        while let Ok(folder) = changes.try_recv() {
+            if !folders.contains(&folder) {
+                folders.push(folder);
             }
         }
+        for folder in folders {
+            sync_and_notify(&app, folder).await;
+        }
     }
-    Ok(())
 }
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
-    let pool = estabilish_connection()
+    let cli = Cli::parse();
+    let app_dir = app_dir();
+    let pool = estabilish_connection(&app_dir)
         .await
         .expect("Failed to connect to database");
     let db = Db::new(pool);
-    let state = AppState { db };
+    let config_path = app_dir.join("config.json");
+    let config_created = !config_path.exists();
+    let (config, config_error) = match Config::load(&config_path) {
+        Ok(config) => (config, None),
+        Err(e) if cli.command.is_some() => {
+            eprintln!("Error: {}", chained_errs_to_string(e));
+            std::process::exit(1);
+        }
+        Err(e) => {
+            let error = chained_errs_to_string(e);
+            warn!("Using the default config: {}", error);
+            (Config::default(), Some(error))
+        }
+    };
+    if let Some(command) = cli.command {
+        if let Err(e) = cli::run(command, &db, &config).await {
+            eprintln!("Error: {}", chained_errs_to_string(e));
+            std::process::exit(1);
+        }
+        return;
+    }
+    let (watcher, changes) = FolderWatcher::new().expect("Failed to create the folder watcher");
+    if let Err(e) = watcher.watch(&config.synced_folders) {
+        warn!("{}", chained_errs_to_string(e));
+    }
+    let startup = if config.sync_on_startup {
+        config.synced_folders.clone()
+    } else {
+        vec![]
+    };
+    let state = AppState {
+        db,
+        config: RwLock::new(config),
+        config_path,
+        config_error,
+        config_created,
+        watcher,
+        sync_lock: Mutex::new(()),
+    };
     tauri::Builder::default()
-        .setup(|_| Ok(()))
+        .setup(|app| {
+            tauri::async_runtime::spawn(sync_changes(app.handle(), startup, changes));
+            Ok(())
+        })
         .manage(state)
         .invoke_handler(tauri::generate_handler![
             get_all_cards,
+            get_tags,
+            get_decks,
             get_cards_to_review,
             answer_question,
             sync_flashcards,
-            get_card
+            preview_sync,
+            lint_folder,
+            get_card,
+            get_config,
+            get_config_error,
+            set_config,
+            migrate_config,
+            optimize_fsrs
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
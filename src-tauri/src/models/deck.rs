@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+/// A deck with the numbers of the cards in it and in its child decks.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Deck {
+    pub name: String,
+    pub cards: usize,
+    // Cards to review, including the new ones.
+    pub due: usize,
+    // Cards which were never answered.
+    pub new: usize,
+}
@@ -1,6 +1,8 @@
+use std::path::{Component, Path};
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Flashcard {
     pub id: Option<i32>,
     pub question: String,
@@ -10,12 +12,81 @@ pub struct Flashcard {
     pub folder: Option<String>,
     // The **absolute** path to the flashcard.
     pub path: Option<String>,
+    // A stable identifier written in the markdown next to the card marker,
+    // for example `#flashcard ^a1b2c3`. Cards are matched by it during sync.
+    pub uid: Option<String>,
+    // Tags written next to the card marker, for example `#flashcard #math`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // The deck from the front matter of the file, otherwise derived from
+    // the path relative to the folder, for example `lang::haskell` for
+    // `lang/haskell.md`.
+    pub deck: Option<String>,
+    // The line of the note which generated more cards, for example a reverse
+    // card or cloze deletions. Cards of the same file and line are siblings.
+    pub note_line: Option<i64>,
+    // The repetition algorithm from the front matter of the file, used
+    // instead of the configured one.
+    pub algorithm: Option<String>,
+}
+
+/// Separates the names of the parent and child decks.
+pub const DECK_SEPARATOR: &str = "::";
+
+/// Returns the deck of the cards in the file, made of the folders and the
+/// file name relative to the synced folder. For example `lang::haskell`
+/// for `notes/lang/haskell.md` synced from `notes`.
+pub fn deck_name(folder: &str, path: &str) -> Option<String> {
+    let relative = Path::new(path)
+        .strip_prefix(folder)
+        .ok()?
+        .with_extension("");
+    let parts: Vec<String> = relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    if parts.is_empty() {
+        return None;
+    }
+    Some(parts.join(DECK_SEPARATOR))
 }
 
 impl Flashcard {
     pub fn with_path(mut self, folder: String, path: String) -> Self {
+        self.deck = self.deck.or_else(|| deck_name(&folder, &path));
         self.folder = Some(folder);
         self.path = Some(path);
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decks() {
+        assert_eq!(
+            deck_name("notes", "notes/lang/haskell.md").as_deref(),
+            Some("lang::haskell")
+        );
+        assert_eq!(
+            deck_name("/notes/", "/notes/./a.b.md").as_deref(),
+            Some("a.b")
+        );
+        assert_eq!(deck_name("/notes", "/other/a.md"), None);
+    }
+
+    #[test]
+    fn front_matter_deck() {
+        let card = Flashcard {
+            deck: Some("haskell".to_string()),
+            ..Default::default()
+        }
+        .with_path("/notes".to_string(), "/notes/lang/a.md".to_string());
+        assert_eq!(card.deck.as_deref(), Some("haskell"));
+    }
+}
@@ -1,7 +1,13 @@
 pub mod answer;
+pub mod deck;
 pub mod flashcard;
+pub mod synced_file;
+pub mod tag;
 
 pub mod prelude {
     pub use super::answer::Answer;
+    pub use super::deck::Deck;
     pub use super::flashcard::Flashcard;
+    pub use super::synced_file::SyncedFile;
+    pub use super::tag::Tag;
 }
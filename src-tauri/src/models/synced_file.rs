@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+/// A markdown file as it was during the last sync. Files with the same
+/// modification time or content are not parsed again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncedFile {
+    pub path: String,
+    // The root folder which was synced.
+    pub folder: String,
+    // Modification time in nanoseconds since the UNIX epoch.
+    pub mtime: i64,
+    pub hash: String,
+    // The parser version which read the file.
+    pub parser_version: i64,
+}
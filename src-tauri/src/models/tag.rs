@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+/// A tag together with the number of cards which are not archived and
+/// have it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tag {
+    pub name: String,
+    pub cards: i64,
+}
@@ -0,0 +1,217 @@
+/// A part of a text with cloze deletions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    /// A deletion written as `{{c1::answer}}`, `{{c1::answer::hint}}`
+    /// or `==answer==`.
+    Cloze {
+        index: usize,
+        answer: &'a str,
+        hint: Option<&'a str>,
+    },
+}
+
+/// Parses `{{cN::answer::hint}}` at the start of the text, returns the
+/// deletion and the length of its syntax.
+fn numbered_cloze(text: &str) -> Option<(Segment<'_>, usize)> {
+    let rest = text.strip_prefix("{{c")?;
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let index = rest[..digits].parse().ok()?;
+    let rest = rest[digits..].strip_prefix("::")?;
+    let end = rest.find("}}")?;
+    let (answer, hint) = match rest[..end].split_once("::") {
+        Some((answer, hint)) => (answer, Some(hint)),
+        None => (&rest[..end], None),
+    };
+    if index == 0 || answer.trim().is_empty() {
+        return None;
+    }
+    let len = text.len() - rest.len() + end + "}}".len();
+    Some((
+        Segment::Cloze {
+            index,
+            answer,
+            hint,
+        },
+        len,
+    ))
+}
+
+/// Parses `==answer==` at the start of the text. The answer cannot start or
+/// end with a space, so that comparisons like `a == b` are not highlights.
+fn highlight(text: &str) -> Option<(&str, usize)> {
+    let rest = text.strip_prefix("==")?;
+    let end = rest.find("==")?;
+    let answer = &rest[..end];
+    if answer.is_empty()
+        || answer.contains('\n')
+        || answer.starts_with(char::is_whitespace)
+        || answer.ends_with(char::is_whitespace)
+    {
+        return None;
+    }
+    Some((answer, end + 2 * "==".len()))
+}
+
+/// Splits the text into the plain text and the cloze deletions. Highlights
+/// are numbered after the largest explicit index, in the order they appear.
+pub fn parse_cloze(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    let mut highlights = vec![];
+    let mut text_start = 0;
+    let mut pos = 0;
+    while let Some(offset) = text[pos..].find(['{', '=']) {
+        let start = pos + offset;
+        let rest = &text[start..];
+        let parsed = if let Some((cloze, len)) = numbered_cloze(rest) {
+            Some((cloze, len))
+        } else if let Some((answer, len)) = highlight(rest) {
+            highlights.push(segments.len() + usize::from(text_start < start));
+            let cloze = Segment::Cloze {
+                index: 0,
+                answer,
+                hint: None,
+            };
+            Some((cloze, len))
+        } else {
+            None
+        };
+        match parsed {
+            Some((cloze, len)) => {
+                if text_start < start {
+                    segments.push(Segment::Text(&text[text_start..start]));
+                }
+                segments.push(cloze);
+                pos = start + len;
+                text_start = pos;
+            }
+            // Skip the whole run of the character, so that `===` is not
+            // read as `=` followed by a highlight.
+            None => pos = start + rest.len() - rest.trim_start_matches(&rest[..1]).len(),
+        }
+    }
+    if text_start < text.len() {
+        segments.push(Segment::Text(&text[text_start..]));
+    }
+
+    let last = cloze_indices(&segments).last().copied().unwrap_or(0);
+    for (i, idx) in highlights.into_iter().enumerate() {
+        if let Segment::Cloze { index, .. } = &mut segments[idx] {
+            *index = last + i + 1;
+        }
+    }
+    segments
+}
+
+/// Returns the sorted unique indices of the deletions.
+pub fn cloze_indices(segments: &[Segment]) -> Vec<usize> {
+    let mut indices: Vec<usize> = segments
+        .iter()
+        .filter_map(|segment| match segment {
+            Segment::Cloze { index, .. } if *index > 0 => Some(*index),
+            _ => None,
+        })
+        .collect();
+    indices.sort_unstable();
+    indices.dedup();
+    indices
+}
+
+/// Returns the question and answer of the card for the deletions with the
+/// index. The deletions are replaced by `[...]`, or by their hint, in the
+/// question and are bold in the answer. Other deletions are shown as text.
+pub fn cloze_card(segments: &[Segment], card_index: usize) -> (String, String) {
+    let mut question = String::new();
+    let mut answer = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => {
+                question.push_str(text);
+                answer.push_str(text);
+            }
+            Segment::Cloze {
+                index,
+                answer: text,
+                hint,
+            } if *index == card_index => {
+                question.push('[');
+                question.push_str(hint.unwrap_or("..."));
+                question.push(']');
+                answer.push_str("**");
+                answer.push_str(text);
+                answer.push_str("**");
+            }
+            Segment::Cloze { answer: text, .. } => {
+                question.push_str(text);
+                answer.push_str(text);
+            }
+        }
+    }
+    (question, answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Segment::*;
+
+    #[test]
+    fn segments() {
+        assert_eq!(
+            parse_cloze("The {{c2::cell}} has ==mitochondria== and {{c1::a nucleus::organelle}}."),
+            vec![
+                Text("The "),
+                Cloze {
+                    index: 2,
+                    answer: "cell",
+                    hint: None
+                },
+                Text(" has "),
+                Cloze {
+                    index: 3,
+                    answer: "mitochondria",
+                    hint: None
+                },
+                Text(" and "),
+                Cloze {
+                    index: 1,
+                    answer: "a nucleus",
+                    hint: Some("organelle")
+                },
+                Text("."),
+            ]
+        );
+    }
+
+    #[test]
+    fn not_cloze() {
+        for text in [
+            "a == b and c == d",
+            "{{c1::}} {{c::x}} {{c0::x}} {{c1::x",
+            "x === y ==",
+            "fn f() {}",
+        ] {
+            assert_eq!(parse_cloze(text), vec![Text(text)]);
+        }
+    }
+
+    #[test]
+    fn cards() {
+        let segments = parse_cloze("{{c1::Rust}} was first released in {{c2::2015::year}}.");
+        assert_eq!(cloze_indices(&segments), vec![1, 2]);
+        assert_eq!(
+            cloze_card(&segments, 1),
+            (
+                "[...] was first released in 2015.".to_string(),
+                "**Rust** was first released in 2015.".to_string()
+            )
+        );
+        assert_eq!(
+            cloze_card(&segments, 2),
+            (
+                "Rust was first released in [year].".to_string(),
+                "Rust was first released in **2015**.".to_string()
+            )
+        );
+    }
+}
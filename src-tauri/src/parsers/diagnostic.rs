@@ -0,0 +1,97 @@
+use std::fmt;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum Severity {
+    /// The card cannot be used, the file is not synced.
+    Error,
+    /// The card is used, but probably not as intended.
+    Warning,
+}
+
+/// A problem found in a markdown file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    /// The file path, None until the markdown is known to come from a file.
+    pub file: Option<String>,
+    /// The line, starting from 1. Zero if the diagnostic is about the whole
+    /// file, for example when it cannot be read.
+    pub line: usize,
+    /// The column in characters, starting from 1.
+    pub column: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(line: usize, column: usize, message: impl ToString) -> Self {
+        Self {
+            file: None,
+            line,
+            column,
+            severity: Severity::Error,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn warning(line: usize, column: usize, message: impl ToString) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(line, column, message)
+        }
+    }
+
+    pub fn in_file(self, file: &str) -> Self {
+        Self {
+            file: Some(file.to_string()),
+            ..self
+        }
+    }
+
+    /// Converts an error from reading or parsing the file. The location is
+    /// kept if the error comes from a diagnostic.
+    pub fn from_error(file: &str, err: anyhow::Error) -> Self {
+        match err.chain().find_map(|e| e.downcast_ref::<Diagnostic>()) {
+            Some(diagnostic) => diagnostic.clone().in_file(file),
+            None => Self::error(0, 0, format!("{:#}", err)).in_file(file),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+        if self.line > 0 {
+            write!(f, "{}:{}:", self.line, self.column)?;
+        }
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, " {}: {}", severity, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let diagnostic = Diagnostic::warning(3, 7, "Card is not terminated").in_file("a.md");
+        assert_eq!(
+            diagnostic.to_string(),
+            "a.md:3:7: warning: Card is not terminated"
+        );
+        let err = anyhow::anyhow!("No such file").context("Failed to read");
+        assert_eq!(
+            Diagnostic::from_error("b.md", err).to_string(),
+            "b.md: error: Failed to read: No such file"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_yaml::Mapping;
+
+use crate::models::flashcard::DECK_SEPARATOR;
+use crate::parsers::diagnostic::Diagnostic;
+use crate::parsers::markdown::tag_name;
+use crate::repetition_algs::prelude::AlgorithmConfig;
+use crate::repetition_algs::registry::ALGORITHM_NAMES;
+
+/// Settings of all cards in a file, written in its YAML front matter:
+/// ```text
+/// ---
+/// deck: lang::haskell
+/// tags: [haskell, fp]
+/// algorithm: SuperMemo 2
+/// reverse: true
+/// disabled: false
+/// ---
+/// ```
+/// Other keys of the front matter are ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrontMatter {
+    /// The deck of the cards instead of the one derived from the path.
+    pub deck: Option<String>,
+    /// Tags added to the tags of every card.
+    pub tags: Vec<String>,
+    /// The name of the repetition algorithm of the cards.
+    pub algorithm: Option<String>,
+    /// Every card is also reviewed from the answer to the question.
+    pub reverse: bool,
+    /// The file has no cards.
+    pub disabled: bool,
+}
+
+/// Tags can be a list or a single string, `tags: a, b` or `tags: [a, b]`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Tags {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Returns the line of the key in the front matter, for the diagnostics.
+fn key_line(lines: &[&str], key: &str) -> usize {
+    lines
+        .iter()
+        .position(|line| {
+            line.strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start().starts_with(':'))
+        })
+        .map_or(1, |idx| idx + 1)
+}
+
+/// Reads the value of the key, a value of a wrong type is reported as
+/// a warning and left out.
+fn value<T: DeserializeOwned>(
+    mapping: &Mapping,
+    key: &str,
+    lines: &[&str],
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<T> {
+    let value = mapping.get(key)?.clone();
+    match serde_yaml::from_value(value) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            let message = format!("Invalid {}: {}", key, e);
+            diagnostics.push(Diagnostic::warning(key_line(lines, key), 1, message));
+            None
+        }
+    }
+}
+
+/// Reads the front matter from its lines, including the `---` delimiters,
+/// which are the first lines of the markdown. Invalid values are reported
+/// as warnings and left out, the cards of the file are still read.
+pub fn parse_front_matter(lines: &[&str]) -> (FrontMatter, Vec<Diagnostic>) {
+    let mut diagnostics = vec![];
+    let yaml = lines
+        .get(1..lines.len().saturating_sub(1))
+        .unwrap_or_default()
+        .join("\n");
+    if yaml.trim().is_empty() {
+        return (FrontMatter::default(), diagnostics);
+    }
+    let mapping: Mapping = match serde_yaml::from_str(&yaml) {
+        Ok(mapping) => mapping,
+        Err(e) => {
+            // The location is in the YAML, which starts on the second line.
+            let (line, column) = e
+                .location()
+                .map_or((1, 1), |location| (location.line() + 1, location.column()));
+            let message = format!("Invalid front matter: {}", e);
+            diagnostics.push(Diagnostic::warning(line, column, message));
+            return (FrontMatter::default(), diagnostics);
+        }
+    };
+
+    let mut front_matter = FrontMatter {
+        reverse: value(&mapping, "reverse", lines, &mut diagnostics).unwrap_or_default(),
+        disabled: value(&mapping, "disabled", lines, &mut diagnostics).unwrap_or_default(),
+        ..Default::default()
+    };
+    if let Some(deck) = value::<String>(&mapping, "deck", lines, &mut diagnostics) {
+        let parts: Vec<&str> = deck.split(DECK_SEPARATOR).map(str::trim).collect();
+        if parts.iter().any(|part| part.is_empty()) {
+            let message = format!("Invalid deck '{}'", deck);
+            diagnostics.push(Diagnostic::warning(key_line(lines, "deck"), 1, message));
+        } else {
+            front_matter.deck = Some(parts.join(DECK_SEPARATOR));
+        }
+    }
+    let tags = match value(&mapping, "tags", lines, &mut diagnostics) {
+        Some(Tags::One(tags)) => tags
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|tag| !tag.is_empty())
+            .map(String::from)
+            .collect(),
+        Some(Tags::Many(tags)) => tags,
+        None => vec![],
+    };
+    for tag in tags {
+        let tag = tag.trim();
+        let tag = format!("#{}", tag.strip_prefix('#').unwrap_or(tag));
+        let Some(name) = tag_name(&tag) else {
+            let message = format!("Invalid tag '{}'", tag);
+            diagnostics.push(Diagnostic::warning(key_line(lines, "tags"), 1, message));
+            continue;
+        };
+        if !front_matter
+            .tags
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case(name))
+        {
+            front_matter.tags.push(name.to_string());
+        }
+    }
+    if let Some(algorithm) = value::<String>(&mapping, "algorithm", lines, &mut diagnostics) {
+        match AlgorithmConfig::default().with_name(algorithm.trim()) {
+            Some(config) => front_matter.algorithm = Some(config.name().to_string()),
+            None => {
+                let message = format!(
+                    "Unknown algorithm '{}', expected one of {}",
+                    algorithm,
+                    ALGORITHM_NAMES.join(", ")
+                );
+                diagnostics.push(Diagnostic::warning(
+                    key_line(lines, "algorithm"),
+                    1,
+                    message,
+                ));
+            }
+        }
+    }
+    (front_matter, diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::diagnostic::Severity;
+
+    fn parse(yaml: &str) -> (FrontMatter, Vec<Diagnostic>) {
+        let content = format!("---\n{}---\n", yaml);
+        let lines: Vec<&str> = content.lines().collect();
+        parse_front_matter(&lines)
+    }
+
+    #[test]
+    fn settings() {
+        let (front_matter, diagnostics) = parse(
+            "title: Haskell\ndeck: lang::haskell\ntags: [fp, '#haskell', FP]\nalgorithm: supermemo 2\nreverse: true\n",
+        );
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            front_matter,
+            FrontMatter {
+                deck: Some("lang::haskell".to_string()),
+                tags: vec!["fp".to_string(), "haskell".to_string()],
+                algorithm: Some("SuperMemo 2".to_string()),
+                reverse: true,
+                disabled: false,
+            }
+        );
+        assert_eq!(parse("tags: a, b\n").0.tags, vec!["a", "b"]);
+        assert_eq!(parse("").0, FrontMatter::default());
+    }
+
+    #[test]
+    fn invalid() {
+        let (front_matter, diagnostics) =
+            parse("deck: '::a'\ntags: [ok, not a tag]\nalgorithm: Leitner\n");
+        assert_eq!(front_matter.tags, vec!["ok"]);
+        assert_eq!(front_matter.deck, None);
+        let lines: Vec<usize> = diagnostics.iter().map(|d| d.line).collect();
+        assert_eq!(lines, vec![2, 3, 4]);
+
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Warning));
+
+        // Only the invalid key is left out.
+        let (front_matter, diagnostics) = parse("title: a\nreverse: maybe\ndisabled: true\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 3);
+        assert!(!front_matter.reverse);
+        assert!(front_matter.disabled);
+    }
+}
@@ -0,0 +1,201 @@
+use std::ops::Range;
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+use crate::parsers::markdown::MARKER;
+
+/// How a markdown line is read when looking for cards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    /// A line of a paragraph, a list or a table, or an empty line.
+    Text,
+    /// A line of a code or HTML block, including the fences. It is never
+    /// a marker or a separator.
+    Code,
+    /// A thematic break, or the `---` underline of a setext heading.
+    /// Separates the questions and answers of cards.
+    Break,
+    /// An ATX heading, or the `===` underline of a setext heading.
+    Heading,
+    /// A line of the YAML front matter, including the delimiters.
+    FrontMatter,
+}
+
+/// A line of a markdown, read by a CommonMark parser.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkdownLine<'a> {
+    /// The line without the line ending.
+    pub text: &'a str,
+    pub kind: LineKind,
+    /// Byte ranges of the inline code spans in the line, including the
+    /// backticks. Their text is never a marker or a tag.
+    pub code_spans: Vec<Range<usize>>,
+}
+
+/// Splits the markdown into lines and determines what each of them is.
+pub fn markdown_lines(content: &str) -> Vec<MarkdownLine<'_>> {
+    let mut starts = vec![];
+    let mut lines = vec![];
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        starts.push(offset);
+        offset += line.len();
+        let text = line.strip_suffix('\n').unwrap_or(line);
+        lines.push(MarkdownLine {
+            text: text.strip_suffix('\r').unwrap_or(text),
+            kind: LineKind::Text,
+            code_spans: vec![],
+        });
+    }
+    let line_of = |offset: usize| starts.partition_point(|&start| start <= offset) - 1;
+
+    // The parser would read a metadata block anywhere in the markdown, but
+    // only the start of the file is the front matter.
+    let body = match front_matter_len(&lines) {
+        Some(len) => {
+            for line in &mut lines[..len] {
+                line.kind = LineKind::FrontMatter;
+            }
+            starts.get(len).copied().unwrap_or(content.len())
+        }
+        None => 0,
+    };
+    let parser = Parser::new_ext(&content[body..], Options::ENABLE_TABLES);
+    for (event, range) in parser.into_offset_iter() {
+        if range.is_empty() {
+            continue;
+        }
+        let range = body + range.start..body + range.end;
+        let (first, last) = (line_of(range.start), line_of(range.end - 1));
+        let kind = match event {
+            Event::Start(Tag::CodeBlock(_) | Tag::HtmlBlock) => LineKind::Code,
+            Event::Rule => LineKind::Break,
+            // A heading over more lines is a setext heading, only its
+            // underline is not text.
+            Event::Start(Tag::Heading { .. }) if first < last => {
+                let underline = &mut lines[last];
+                underline.kind = if underline.text.trim_start().starts_with('-') {
+                    LineKind::Break
+                } else {
+                    LineKind::Heading
+                };
+                continue;
+            }
+            Event::Start(Tag::Heading { .. }) => LineKind::Heading,
+            Event::Code(_) => {
+                for (idx, line) in lines.iter_mut().enumerate().take(last + 1).skip(first) {
+                    let start = range.start.max(starts[idx]) - starts[idx];
+                    let end = (range.end - starts[idx]).min(line.text.len());
+                    line.code_spans.push(start..end);
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        for line in &mut lines[first..=last] {
+            line.kind = kind;
+        }
+    }
+    lines
+}
+
+/// Returns the number of lines of the YAML front matter, including the
+/// `---` delimiters, if the markdown starts with one. A block which is not
+/// a YAML mapping, or which has a card marker, is a card between thematic
+/// breaks, such as `---\nq #flashcard\na\n---`.
+fn front_matter_len(lines: &[MarkdownLine]) -> Option<usize> {
+    if lines.first()?.text.trim_end() != "---" {
+        return None;
+    }
+    let end = lines
+        .iter()
+        .skip(1)
+        .position(|line| matches!(line.text.trim_end(), "---" | "..."))?;
+    let content = &lines[1..end + 1];
+    if content.iter().any(|line| line.text.contains(MARKER)) {
+        return None;
+    }
+    let yaml = content
+        .iter()
+        .map(|line| line.text)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if !yaml.trim().is_empty() && serde_yaml::from_str::<serde_yaml::Mapping>(&yaml).is_err() {
+        return None;
+    }
+    Some(end + 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use LineKind::*;
+
+    #[test]
+    fn kinds() {
+        let markdown = "---\ntags: [a]\n---\n# Title\nq #flashcard\na\n---\n```md\n---\n```\n\n- - -\na | b\n--- | ---\nx `#flashcard` y\n\nTitle\n===\n";
+        let lines = markdown_lines(markdown);
+        let kinds: Vec<LineKind> = lines.iter().map(|line| line.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                FrontMatter,
+                FrontMatter,
+                FrontMatter,
+                Heading,
+                Text,
+                Text,
+                Break,
+                Code,
+                Code,
+                Code,
+                Text,
+                Break,
+                Text,
+                Text,
+                Text,
+                Text,
+                Text,
+                Heading,
+            ]
+        );
+        assert_eq!(lines[14].text, "x `#flashcard` y");
+        assert_eq!(lines[14].code_spans, vec![2..14]);
+    }
+
+    #[test]
+    fn front_matter() {
+        let lines = markdown_lines("# Title\n---\nnot: front matter\n---\n");
+        let kinds: Vec<LineKind> = lines.iter().map(|line| line.kind).collect();
+        assert_eq!(kinds, vec![Heading, Break, Text, Break]);
+
+        // An unterminated front matter is a thematic break.
+        let lines = markdown_lines("---\nq #flashcard\n");
+        let kinds: Vec<LineKind> = lines.iter().map(|line| line.kind).collect();
+        assert_eq!(kinds, vec![Break, Text]);
+
+        // A card between thematic breaks is not front matter.
+        let lines = markdown_lines("---\nq #flashcard\na\n---\n");
+        let kinds: Vec<LineKind> = lines.iter().map(|line| line.kind).collect();
+        assert_eq!(kinds, vec![Break, Text, Text, Break]);
+        let lines = markdown_lines("---\nQ: q #flashcard\nA: a\n---\n");
+        let kinds: Vec<LineKind> = lines.iter().map(|line| line.kind).collect();
+        assert_eq!(kinds, vec![Break, Text, Text, Break]);
+        let lines = markdown_lines("---\nsome text\n---\n");
+        let kinds: Vec<LineKind> = lines.iter().map(|line| line.kind).collect();
+        assert_eq!(kinds, vec![Break, Text, Break]);
+
+        let lines = markdown_lines("---\n---\nq #flashcard\n");
+        let kinds: Vec<LineKind> = lines.iter().map(|line| line.kind).collect();
+        assert_eq!(kinds, vec![FrontMatter, FrontMatter, Text]);
+    }
+
+    #[test]
+    fn line_endings() {
+        let lines = markdown_lines("a\r\nb");
+        let texts: Vec<&str> = lines.iter().map(|line| line.text).collect();
+        assert_eq!(texts, vec!["a", "b"]);
+        assert_eq!(lines[0].kind, Text);
+        assert!(markdown_lines("").is_empty());
+    }
+}
@@ -1,106 +1,693 @@
-use tokio::io::{AsyncBufRead, AsyncBufReadExt, Lines};
+use std::ops::Range;
+
+use tokio::io::{AsyncBufRead, AsyncReadExt};
 
 use crate::models::flashcard::Flashcard;
-use anyhow::{bail, Result};
-
-pub async fn read_until_hr<R: AsyncBufRead + Unpin>(lines: &mut Lines<R>) -> Result<String> {
-    let mut text = String::new();
-    while let Some(line) = lines.next_line().await? {
-        // TODO: Something more generic for MD lines.
-        if line.starts_with("---")
-            || line.starts_with("- - -")
-            || line.starts_with("***")
-            || line.starts_with("* * *")
-        {
-            break;
-        }
-        text.push_str(line.trim());
-        text.push('\n');
-    }
-    Ok(text)
-}
-
-/**
- * Parse a long question, expecting a following format:
- * #flashcard [#tags ...] (This line should already be read and tags passed!)
- * question text (possibly over multiple lines)
- * - - - (A line in markdown)
- * answer text (possibly over multiple lines)
- * - - - (A line in markdown)
- */
-pub async fn long_question<R: AsyncBufRead + Unpin>(
-    reader: &mut Lines<R>,
-    _tags: Vec<String>,
-) -> Result<Flashcard> {
-    let question = read_until_hr(reader).await?;
-    let answer = read_until_hr(reader).await?;
-
-    if question.is_empty() || answer.is_empty() {
-        bail!("Card cannot have an empty question or answer text");
-    }
-
-    Ok(Flashcard {
-        id: None,
-        question,
-        answer,
-        folder: None,
-        path: None,
+use crate::parsers::cloze::{cloze_card, cloze_indices, parse_cloze};
+use crate::parsers::diagnostic::{Diagnostic, Severity};
+use crate::parsers::front_matter::{parse_front_matter, FrontMatter};
+use crate::parsers::lines::{markdown_lines, LineKind, MarkdownLine};
+use anyhow::Result;
+
+/// The marker of a card.
+pub const MARKER: &str = "#flashcard";
+
+/// The marker of a card which is also reviewed from the answer to the question.
+const REVERSE_MARKER: &str = "#flashcard/reverse";
+
+/// Separates the ID written next to the marker from the suffix of the other
+/// cards of the same note, for example `a1b2c3:c2` for the second cloze
+/// deletion or `a1b2c3:r` for the reverse card.
+pub const CARD_ID_SEPARATOR: char = ':';
+
+/// Splits the line into words separated by whitespace, together with their
+/// byte offsets.
+fn words(line: &str) -> Vec<(usize, &str)> {
+    let mut words = vec![];
+    let mut start = None;
+    for (idx, c) in line.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(word_start)) => {
+                words.push((word_start, &line[word_start..idx]));
+                start = None;
+            }
+            (false, None) => start = Some(idx),
+            _ => {}
+        }
+    }
+    if let Some(word_start) = start {
+        words.push((word_start, &line[word_start..]));
+    }
+    words
+}
+
+fn is_marker(word: &str) -> bool {
+    word == MARKER || word == REVERSE_MARKER
+}
+
+/// Returns the name of the tag if the word is one, for example `math`
+/// for `#math`. Numbers like `#123` and the markers are not tags.
+pub(crate) fn tag_name(word: &str) -> Option<&str> {
+    let tag = word.strip_prefix('#')?;
+    let valid = !tag.is_empty()
+        && tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '/')
+        && !tag.chars().all(|c| c.is_ascii_digit())
+        && tag != "flashcard"
+        && !tag.starts_with("flashcard/");
+    valid.then_some(tag)
+}
+
+/// The line with the `#flashcard` marker, for example
+/// `question #math #flashcard #algebra ^a1b2c3`.
+#[derive(Debug, Clone, PartialEq)]
+struct MarkerLine {
+    /// The text before the marker and the tags written right before it.
+    text: String,
+    tags: Vec<String>,
+    uid: Option<String>,
+    /// Whether the card is also reviewed from the answer to the question.
+    reverse: bool,
+    /// The column of the marker in characters, starting from 1.
+    column: usize,
+}
+
+/// Reads the marker line, words in the code spans are only text.
+fn marker_line(line: &str, code_spans: &[Range<usize>]) -> Option<MarkerLine> {
+    let in_code = |offset: usize, word: &str| {
+        code_spans
+            .iter()
+            .any(|span| span.start < offset + word.len() && offset < span.end)
+    };
+    let words: Vec<(usize, &str)> = words(line)
+        .into_iter()
+        .map(|(offset, word)| (offset, if in_code(offset, word) { "" } else { word }))
+        .collect();
+    let marker = words.iter().position(|(_, word)| is_marker(word))?;
+    // Tags right before the marker belong to the card, not to its text.
+    let mut start = marker;
+    while start > 0 && tag_name(words[start - 1].1).is_some() {
+        start -= 1;
+    }
+
+    let mut tags: Vec<String> = vec![];
+    for tag in words[start..].iter().filter_map(|(_, word)| tag_name(word)) {
+        if !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            tags.push(tag.to_string());
+        }
+    }
+    let uid = words[marker + 1..]
+        .iter()
+        .filter_map(|(_, word)| word.strip_prefix('^'))
+        .find(|id| {
+            !id.is_empty()
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .map(|id| id.to_string());
+    Some(MarkerLine {
+        text: line[..words[start].0].trim().to_string(),
+        tags,
+        uid,
+        reverse: words[start..]
+            .iter()
+            .any(|(_, word)| *word == REVERSE_MARKER),
+        column: line[..words[marker].0].chars().count() + 1,
     })
 }
 
-pub async fn read_markdown<R: AsyncBufRead + Unpin>(reader: R) -> Result<Vec<Flashcard>> {
-    let mut flashcards = vec![];
-    let mut line_it = reader.lines();
-    loop {
-        let line = line_it.next_line().await?;
-        if line.is_none() {
-            break;
-        }
-        let line = line.unwrap();
-        // Try to parse the format:
-        // ```
-        // question text ... [#tags ...] #flashcard [#tags ...]
-        // answer text (possibly over multiple lines)
-        // - - - (A line in markdown)
-        // Or the format
-        // #flashcard [#tags ...]
-        // question text (possibly over multiple lines)
-        // - - - (A line in markdown)
-        // answer text (possibly over multiple lines)
-        // - - - (A line in markdown)
-        // ```
-        if line.trim().starts_with("#flashcard") {
-            let it = line.split('#');
-            let tags: Vec<String> = it.map(|s| s.to_string()).collect();
-            let card = long_question(&mut line_it, tags).await?;
-            flashcards.push(card);
+/// Splits the text of a single line card, `question :: answer`. The answer
+/// of `question ::: answer` is also a question.
+fn split_single_line(text: &str) -> Option<(&str, &str, bool)> {
+    for (separator, reverse) in [(" ::: ", true), (" :: ", false)] {
+        if let Some((question, answer)) = text.split_once(separator) {
+            let (question, answer) = (question.trim(), answer.trim());
+            if !question.is_empty() && !answer.is_empty() {
+                return Some((question, answer, reverse));
+            }
+        }
+    }
+    None
+}
+
+/// Cards and problems found in a markdown.
+#[derive(Debug, Default)]
+pub struct ParsedMarkdown {
+    /// The cards with the line of their `#flashcard` marker, starting from 1.
+    pub cards: Vec<(usize, Flashcard)>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Removes the empty lines around the text, keeping the indentation of its
+/// first line and the line ending of its last line.
+fn trim_blank_lines(text: &str) -> String {
+    let start: usize = text
+        .split_inclusive('\n')
+        .take_while(|line| line.trim().is_empty())
+        .map(str::len)
+        .sum();
+    let text = &text[start..];
+    let end = text.trim_end().len();
+    let end = text[end..]
+        .find('\n')
+        .map_or(text.len(), |idx| end + idx + 1);
+    text[..end].to_string()
+}
+
+/// A card whose marker line was read.
+#[derive(Debug)]
+struct StartedCard {
+    /// The line of the marker, starting from 1.
+    line: usize,
+    marker: MarkerLine,
+}
+
+/// What the parser is reading. Every card starts at the line with its
+/// marker. The supported formats are:
+/// ```text
+/// question text ... [#tags ...] #flashcard [#tags ...] [^id]
+/// answer text (possibly over multiple lines)
+/// - - - (A line in markdown)
+///
+/// question text ... #flashcard
+/// more question text
+/// ? (?? for a reverse card)
+/// answer text (possibly over multiple lines)
+/// - - -
+///
+/// #flashcard [#tags ...] [^id]
+/// question text (possibly over multiple lines)
+/// - - -
+/// answer text (possibly over multiple lines)
+/// - - -
+///
+/// Q: question text (possibly over multiple lines) ... #flashcard
+/// more question text
+/// A: answer text
+/// more answer text
+/// (an empty line)
+///
+/// question :: answer #flashcard (::: for a reverse card, the line is the card)
+///
+/// text with {{c1::cloze}} deletions
+/// or ==highlights== ... #flashcard (the paragraph is the card)
+/// ```
+/// Any card is reviewed in both directions with `#flashcard/reverse`.
+#[derive(Debug)]
+enum State {
+    /// Outside of cards, with the lines of the current paragraph.
+    Text { paragraph: Vec<String> },
+    /// The answer of a card with the question on the marker line. If a `?`
+    /// line is found, the lines before it are part of the question.
+    InlineAnswer {
+        card: StartedCard,
+        question: String,
+        answer: String,
+        separated: bool,
+    },
+    /// The question of a card with the marker on its own line.
+    LongQuestion { card: StartedCard, question: String },
+    LongAnswer {
+        card: StartedCard,
+        question: String,
+        answer: String,
+    },
+    /// The question of a `Q:` card, until a line starting with `A:`.
+    PrefixedQuestion { card: StartedCard, question: String },
+    /// The answer of a `Q:` card, until an empty line.
+    PrefixedAnswer {
+        card: StartedCard,
+        question: String,
+        answer: String,
+    },
+}
+
+/// Reads the cards from the lines of a markdown one by one.
+struct CardParser {
+    state: State,
+    parsed: ParsedMarkdown,
+    /// All cards are reversed, set by the front matter.
+    reverse: bool,
+}
+
+impl CardParser {
+    fn new(reverse: bool) -> Self {
+        Self {
+            state: State::Text { paragraph: vec![] },
+            parsed: ParsedMarkdown::default(),
+            reverse,
+        }
+    }
+
+    fn error(&mut self, card: &StartedCard, column: usize, message: &str) {
+        self.parsed
+            .diagnostics
+            .push(Diagnostic::error(card.line, column, message));
+    }
+
+    /// Adds the card, and its reverse if it has the reverse marker.
+    fn push_card(&mut self, card: &StartedCard, question: String, answer: String, reverse: bool) {
+        let reverse = reverse || card.marker.reverse || self.reverse;
+        let note_line = reverse.then_some(card.line as i64);
+        if reverse {
+            let reverse_card = Flashcard {
+                question: answer.clone(),
+                answer: question.clone(),
+                uid: card
+                    .marker
+                    .uid
+                    .as_ref()
+                    .map(|id| format!("{}{}r", id, CARD_ID_SEPARATOR)),
+                tags: card.marker.tags.clone(),
+                note_line,
+                ..Default::default()
+            };
+            self.parsed.cards.push((
+                card.line,
+                Flashcard {
+                    question,
+                    answer,
+                    uid: card.marker.uid.clone(),
+                    tags: card.marker.tags.clone(),
+                    note_line,
+                    ..Default::default()
+                },
+            ));
+            self.parsed.cards.push((card.line, reverse_card));
         } else {
-            let mut it = line.split('#');
-            let question = it.next().unwrap().trim().to_string();
-            let tags: Vec<String> = it.map(|s| s.to_string()).collect();
-            // Only after this we are certain that this is a flashcard
-            if !tags.into_iter().any(|s| s.starts_with("flashcard")) {
-                continue;
+            self.parsed.cards.push((
+                card.line,
+                Flashcard {
+                    question,
+                    answer,
+                    uid: card.marker.uid.clone(),
+                    tags: card.marker.tags.clone(),
+                    ..Default::default()
+                },
+            ));
+        }
+    }
+
+    /// Adds a card for every cloze index of the text.
+    fn push_cloze_cards(&mut self, card: &StartedCard, text: &str) {
+        let segments = parse_cloze(text);
+        for index in cloze_indices(&segments) {
+            let (question, answer) = cloze_card(&segments, index);
+            let cloze = Flashcard {
+                question,
+                answer,
+                // Cards of the deletions share the written ID.
+                uid: card
+                    .marker
+                    .uid
+                    .as_ref()
+                    .map(|id| format!("{}{}c{}", id, CARD_ID_SEPARATOR, index)),
+                tags: card.marker.tags.clone(),
+                note_line: Some(card.line as i64),
+                ..Default::default()
+            };
+            self.parsed.cards.push((card.line, cloze));
+        }
+    }
+
+    /// Adds a `question :: answer` card, returns false if the question is
+    /// not one.
+    fn push_single_line(&mut self, card: &StartedCard, text: &str) -> bool {
+        match split_single_line(text) {
+            Some((question, answer, reverse)) => {
+                self.push_card(card, question.to_string(), answer.to_string(), reverse);
+                true
             }
+            None => false,
+        }
+    }
 
-            if question.is_empty() {
-                bail!("Card cannot have empty question text");
+    /// Validates and adds a card with the question on the marker line.
+    /// Returns whether the card was added.
+    fn finish_inline(&mut self, card: StartedCard, question: String, answer: String) -> bool {
+        let (question, answer) = (trim_blank_lines(&question), trim_blank_lines(&answer));
+        if question.is_empty() {
+            self.error(&card, 1, "Card cannot have empty question text");
+            return false;
+        }
+        if answer.is_empty() {
+            if self.push_single_line(&card, &question) {
+                return true;
             }
+            self.error(
+                &card,
+                card.marker.column,
+                "Card cannot have empty answer text",
+            );
+            return false;
+        }
+        self.push_card(&card, question, answer, false);
+        true
+    }
 
-            let answer = read_until_hr(&mut line_it).await?;
-            if answer.is_empty() {
-                bail!("Card cannot have empty answer text");
+    fn finish_long(&mut self, card: StartedCard, question: String, answer: String) -> bool {
+        let (question, answer) = (trim_blank_lines(&question), trim_blank_lines(&answer));
+        if question.is_empty() || answer.is_empty() {
+            self.error(
+                &card,
+                card.marker.column,
+                "Card cannot have an empty question or answer text",
+            );
+            return false;
+        }
+        self.push_card(&card, question, answer, false);
+        true
+    }
+
+    fn finish_prefixed(&mut self, card: StartedCard, question: String, answer: String) {
+        let (question, answer) = (trim_blank_lines(&question), trim_blank_lines(&answer));
+        if question.is_empty() || answer.is_empty() {
+            self.error(
+                &card,
+                card.marker.column,
+                "Card cannot have an empty question or answer text",
+            );
+            return;
+        }
+        self.push_card(&card, question, answer, false);
+    }
+
+    /// Starts a card at the marker line.
+    fn start_card(&mut self, mut paragraph: Vec<String>, card: StartedCard) -> State {
+        let text = card.marker.text.clone();
+        if text.is_empty() {
+            return State::LongQuestion {
+                card,
+                question: String::new(),
+            };
+        }
+        // The deletions may be on any line of the paragraph.
+        let cloze_text = paragraph
+            .iter()
+            .chain([&text])
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !cloze_indices(&parse_cloze(&cloze_text)).is_empty() {
+            self.push_cloze_cards(&card, &cloze_text);
+            return State::Text { paragraph: vec![] };
+        }
+        // The `Q:` prefix either starts the paragraph or the marker line.
+        if paragraph
+            .first()
+            .is_some_and(|line| line.trim_start().starts_with("Q:"))
+        {
+            paragraph.push(text.clone());
+        } else {
+            paragraph = vec![text.clone()];
+        }
+        if let Some(question) = paragraph.join("\n").trim_start().strip_prefix("Q:") {
+            return State::PrefixedQuestion {
+                question: question.trim_start().to_string(),
+                card,
+            };
+        }
+        State::InlineAnswer {
+            card,
+            question: text,
+            answer: String::new(),
+            separated: false,
+        }
+    }
+
+    /// Reads the next line, starting from 1.
+    fn line(&mut self, line_number: usize, line: &MarkdownLine) {
+        if line.kind == LineKind::FrontMatter {
+            return;
+        }
+        let state = std::mem::replace(&mut self.state, State::Text { paragraph: vec![] });
+        let trimmed = line.text.trim();
+        let separator = line.kind == LineKind::Break;
+        // Markup of the cards is only read from text, not from code.
+        let text = line.kind == LineKind::Text;
+        // Empty lines in code blocks do not end answers.
+        let empty = text && trimmed.is_empty();
+        self.state = match state {
+            State::Text { mut paragraph } => {
+                if !text || empty {
+                    State::Text { paragraph: vec![] }
+                } else if let Some(marker) = marker_line(line.text, &line.code_spans) {
+                    let card = StartedCard {
+                        line: line_number,
+                        marker,
+                    };
+                    self.start_card(paragraph, card)
+                } else {
+                    paragraph.push(line.text.to_string());
+                    State::Text { paragraph }
+                }
             }
-            flashcards.push(Flashcard {
-                id: None,
+            State::InlineAnswer {
+                card,
+                mut question,
+                mut answer,
+                separated,
+            } => {
+                if separator {
+                    self.finish_inline(card, question, answer);
+                    State::Text { paragraph: vec![] }
+                } else if text && !separated && (trimmed == "?" || trimmed == "??") {
+                    let lines = answer.trim_end_matches('\n');
+                    if !lines.is_empty() {
+                        question.push('\n');
+                        question.push_str(lines);
+                    }
+                    let card = StartedCard {
+                        marker: MarkerLine {
+                            reverse: card.marker.reverse || trimmed == "??",
+                            ..card.marker
+                        },
+                        ..card
+                    };
+                    State::InlineAnswer {
+                        card,
+                        question,
+                        answer: String::new(),
+                        separated: true,
+                    }
+                } else if empty
+                    && answer.is_empty()
+                    && !separated
+                    && self.push_single_line(&card, &question)
+                {
+                    State::Text { paragraph: vec![] }
+                } else {
+                    answer.push_str(line.text);
+                    answer.push('\n');
+                    State::InlineAnswer {
+                        card,
+                        question,
+                        answer,
+                        separated,
+                    }
+                }
+            }
+            State::LongQuestion { card, mut question } => {
+                if separator {
+                    State::LongAnswer {
+                        card,
+                        question,
+                        answer: String::new(),
+                    }
+                } else {
+                    question.push_str(line.text);
+                    question.push('\n');
+                    State::LongQuestion { card, question }
+                }
+            }
+            State::LongAnswer {
+                card,
+                question,
+                mut answer,
+            } => {
+                if separator {
+                    self.finish_long(card, question, answer);
+                    State::Text { paragraph: vec![] }
+                } else {
+                    answer.push_str(line.text);
+                    answer.push('\n');
+                    State::LongAnswer {
+                        card,
+                        question,
+                        answer,
+                    }
+                }
+            }
+            State::PrefixedQuestion { card, mut question } => {
+                if let Some(answer) = trimmed.strip_prefix("A:").filter(|_| text) {
+                    let answer = answer.trim();
+                    State::PrefixedAnswer {
+                        card,
+                        question,
+                        answer: if answer.is_empty() {
+                            String::new()
+                        } else {
+                            format!("{}\n", answer)
+                        },
+                    }
+                } else if empty || separator {
+                    self.error(
+                        &card,
+                        card.marker.column,
+                        "Card has no answer, expected a line starting with A:",
+                    );
+                    State::Text { paragraph: vec![] }
+                } else {
+                    question.push('\n');
+                    question.push_str(line.text);
+                    State::PrefixedQuestion { card, question }
+                }
+            }
+            State::PrefixedAnswer {
+                card,
+                question,
+                mut answer,
+            } => {
+                if empty || separator {
+                    self.finish_prefixed(card, question, answer);
+                    State::Text { paragraph: vec![] }
+                } else {
+                    answer.push_str(line.text);
+                    answer.push('\n');
+                    State::PrefixedAnswer {
+                        card,
+                        question,
+                        answer,
+                    }
+                }
+            }
+        };
+    }
+
+    /// Finishes the card which was read at the end of the markdown.
+    fn finish(mut self) -> ParsedMarkdown {
+        let state = std::mem::replace(&mut self.state, State::Text { paragraph: vec![] });
+        let unterminated = match state {
+            State::Text { .. } => None,
+            State::InlineAnswer {
+                card,
+                question,
+                answer,
+                ..
+            } => {
+                let single_line = answer.is_empty();
+                let (line, column) = (card.line, card.marker.column);
+                (self.finish_inline(card, question, answer) && !single_line)
+                    .then_some((line, column))
+            }
+            State::LongQuestion { card, question } => {
+                self.finish_long(card, question, String::new());
+                None
+            }
+            State::LongAnswer {
+                card,
+                question,
+                answer,
+            } => {
+                let (line, column) = (card.line, card.marker.column);
+                self.finish_long(card, question, answer)
+                    .then_some((line, column))
+            }
+            State::PrefixedQuestion { card, .. } => {
+                self.error(
+                    &card,
+                    card.marker.column,
+                    "Card has no answer, expected a line starting with A:",
+                );
+                None
+            }
+            State::PrefixedAnswer {
+                card,
                 question,
                 answer,
-                folder: None,
-                path: None,
-            });
+            } => {
+                self.finish_prefixed(card, question, answer);
+                None
+            }
+        };
+        if let Some((line, column)) = unterminated {
+            self.parsed.diagnostics.push(Diagnostic::warning(
+                line,
+                column,
+                "Card is not terminated by a horizontal rule, the rest of the file is its answer",
+            ));
+        }
+        self.parsed
+    }
+}
+
+pub async fn read_markdown<R: AsyncBufRead + Unpin>(reader: R) -> Result<Vec<Flashcard>> {
+    let cards = read_markdown_with_lines(reader).await?;
+    Ok(cards.into_iter().map(|(_, card)| card).collect())
+}
+
+/// Same as [`read_markdown`], but also returns the number of the line
+/// with the `#flashcard` marker of each card, starting from 1.
+/// Fails with the first error diagnostic of the markdown.
+pub async fn read_markdown_with_lines<R: AsyncBufRead + Unpin>(
+    reader: R,
+) -> Result<Vec<(usize, Flashcard)>> {
+    let parsed = parse_markdown(reader).await?;
+    if let Some(error) = parsed
+        .diagnostics
+        .into_iter()
+        .find(|d| d.severity == Severity::Error)
+    {
+        return Err(error.into());
+    }
+    Ok(parsed.cards)
+}
+
+/// Parses all cards of the markdown. Invalid cards are skipped and reported
+/// as diagnostics, only reading the markdown can fail.
+pub async fn parse_markdown<R: AsyncBufRead + Unpin>(reader: R) -> Result<ParsedMarkdown> {
+    let mut content = String::new();
+    let mut reader = reader;
+    reader.read_to_string(&mut content).await?;
+    let lines = markdown_lines(&content);
+    let front_matter: Vec<&str> = lines
+        .iter()
+        .take_while(|line| line.kind == LineKind::FrontMatter)
+        .map(|line| line.text)
+        .collect();
+    let (front_matter, diagnostics) = parse_front_matter(&front_matter);
+    if front_matter.disabled {
+        return Ok(ParsedMarkdown {
+            cards: vec![],
+            diagnostics,
+        });
+    }
+    let mut parser = CardParser::new(front_matter.reverse);
+    for (idx, line) in lines.iter().enumerate() {
+        parser.line(idx + 1, line);
+    }
+    let mut parsed = parser.finish();
+    for (_, card) in &mut parsed.cards {
+        apply_front_matter(card, &front_matter);
+    }
+    parsed.diagnostics.splice(0..0, diagnostics);
+    Ok(parsed)
+}
+
+/// Sets the deck and the algorithm of the card from the front matter and
+/// puts the tags of the front matter before the tags of the card.
+fn apply_front_matter(card: &mut Flashcard, front_matter: &FrontMatter) {
+    let mut tags = front_matter.tags.clone();
+    for tag in card.tags.drain(..) {
+        if !tags.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+            tags.push(tag);
         }
     }
-    Ok(flashcards)
+    card.tags = tags;
+    card.deck = front_matter.deck.clone();
+    card.algorithm = front_matter.algorithm.clone();
 }
 
 #[cfg(test)]
@@ -168,6 +755,11 @@ mod tests {
         assert_eq!(flashcards.len(), 1);
         assert_eq!(flashcards[0].question, "question");
         assert_eq!(flashcards[0].answer, "answer\n");
+
+        let parsed = parse_markdown(Cursor::new(markdown)).await.unwrap();
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert_eq!(parsed.diagnostics[0].severity, Severity::Warning);
+        assert_eq!(parsed.diagnostics[0].column, 10);
     }
 
     #[tokio::test]
@@ -199,6 +791,254 @@ a normal answer
         assert_eq!(flashcards[1].answer, "a normal answer\n");
     }
 
+    #[tokio::test]
+    async fn test_card_ids() {
+        let markdown = "question #flashcard ^abc-123\nanswer\n---\n#flashcard #tag ^q_2\nquestion2\n---\nanswer2\n---\nquestion3 #flashcard\nanswer3\n---\n";
+        let reader = Cursor::new(markdown);
+        let flashcards = read_markdown(reader).await.unwrap();
+        assert_eq!(flashcards.len(), 3);
+        assert_eq!(flashcards[0].question, "question");
+        assert_eq!(flashcards[0].uid.as_deref(), Some("abc-123"));
+        assert_eq!(flashcards[1].question, "question2\n");
+        assert_eq!(flashcards[1].uid.as_deref(), Some("q_2"));
+        assert_eq!(flashcards[2].uid, None);
+    }
+
+    #[tokio::test]
+    async fn test_card_lines() {
+        let markdown = "text\nquestion #flashcard\nanswer\n---\n\n#flashcard\nq\n---\na\n---\n";
+        let reader = Cursor::new(markdown);
+        let flashcards = read_markdown_with_lines(reader).await.unwrap();
+        let lines: Vec<usize> = flashcards.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, vec![2, 6]);
+    }
+
+    #[tokio::test]
+    async fn test_card_tags() {
+        let markdown = "question #math #flashcard #Algebra ^a1\nanswer\n---\n#flashcard #lang/haskell #math #Math\nq\n---\na\n---\nno tags #flashcard\na\n---\n";
+        let reader = Cursor::new(markdown);
+        let flashcards = read_markdown(reader).await.unwrap();
+        assert_eq!(flashcards[0].tags, vec!["math", "Algebra"]);
+        assert_eq!(flashcards[1].tags, vec!["lang/haskell", "math"]);
+        assert!(flashcards[2].tags.is_empty());
+        let marker = marker_line("issue #123 #a!b # #flashcard", &[]).unwrap();
+        assert!(marker.tags.is_empty());
+    }
+
+    fn card_id(line: &str) -> Option<String> {
+        marker_line(line, &[])?.uid
+    }
+
+    #[test]
+    fn test_card_id_position() {
+        assert_eq!(card_id("What is 2 ^3? #flashcard"), None);
+        assert_eq!(card_id("x #flashcard ^"), None);
+        assert_eq!(card_id("x #flashcard ^a!b"), None);
+        assert_eq!(card_id("x #flashcard ^a1 ^b2").as_deref(), Some("a1"));
+    }
+
+    #[tokio::test]
+    async fn test_cloze() {
+        let markdown = "# Biology\nThe ==mitochondria== is\nthe {{c1::powerhouse}} of the cell. #flashcard #bio ^m1\n\nNot a ==card==.\n\nA ==highlight== only. #flashcard\nq #flashcard\na\n---\n";
+        let reader = Cursor::new(markdown);
+        let flashcards = read_markdown_with_lines(reader).await.unwrap();
+        assert_eq!(flashcards.len(), 4);
+        let (line, card) = &flashcards[0];
+        assert_eq!(*line, 3);
+        assert_eq!(card.question, "The mitochondria is\nthe [...] of the cell.");
+        assert_eq!(
+            card.answer,
+            "The mitochondria is\nthe **powerhouse** of the cell."
+        );
+        assert_eq!(card.uid.as_deref(), Some("m1:c1"));
+        assert_eq!(card.tags, vec!["bio"]);
+        let (_, card) = &flashcards[1];
+        assert_eq!(card.question, "The [...] is\nthe powerhouse of the cell.");
+        assert_eq!(card.uid.as_deref(), Some("m1:c2"));
+        let (line, card) = &flashcards[2];
+        assert_eq!(*line, 7);
+        assert_eq!(card.question, "A [...] only.");
+        assert_eq!(card.answer, "A **highlight** only.");
+        assert_eq!(card.uid, None);
+        assert_eq!(flashcards[3].1.question, "q");
+    }
+
+    #[tokio::test]
+    async fn cloze_before_marker() {
+        let markdown = "The {{c1::mitochondria}} is\nthe powerhouse of the cell. #flashcard\n";
+        let flashcards = read_markdown_with_lines(Cursor::new(markdown))
+            .await
+            .unwrap();
+        assert_eq!(flashcards.len(), 1);
+        let (line, card) = &flashcards[0];
+        assert_eq!(*line, 2);
+        assert_eq!(card.question, "The [...] is\nthe powerhouse of the cell.");
+        assert_eq!(
+            card.answer,
+            "The **mitochondria** is\nthe powerhouse of the cell."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reverse() {
+        let markdown = "der Hund #flashcard/reverse #german ^d1\nthe dog\n---\n#flashcard/reverse\nq\n---\na\n---\n";
+        let reader = Cursor::new(markdown);
+        let flashcards = read_markdown(reader).await.unwrap();
+        assert_eq!(flashcards.len(), 4);
+        assert_eq!(flashcards[0].question, "der Hund");
+        assert_eq!(flashcards[0].uid.as_deref(), Some("d1"));
+        assert_eq!(flashcards[1].question, "the dog\n");
+        assert_eq!(flashcards[1].answer, "der Hund");
+        assert_eq!(flashcards[1].uid.as_deref(), Some("d1:r"));
+        assert_eq!(flashcards[1].tags, vec!["german"]);
+        assert_eq!(flashcards[0].note_line, Some(1));
+        assert_eq!(flashcards[1].note_line, Some(1));
+        assert_eq!(flashcards[3].question, "a\n");
+        assert_eq!(flashcards[3].uid, None);
+        assert_eq!(flashcards[3].note_line, Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_question_separator() {
+        let markdown = "What does it print? #flashcard\nprint(1 + 1)\n?\n2\n---\nq #flashcard ^a1\nmore q\n??\na\n---\nq2 #flashcard\n?\na2\n---\n";
+        let reader = Cursor::new(markdown);
+        let flashcards = read_markdown(reader).await.unwrap();
+        assert_eq!(flashcards.len(), 4);
+        assert_eq!(flashcards[0].question, "What does it print?\nprint(1 + 1)");
+        assert_eq!(flashcards[0].answer, "2\n");
+        assert_eq!(flashcards[1].question, "q\nmore q");
+        assert_eq!(flashcards[2].question, "a\n");
+        assert_eq!(flashcards[2].uid.as_deref(), Some("a1:r"));
+        assert_eq!(flashcards[3].question, "q2");
+        assert_eq!(flashcards[3].answer, "a2\n");
+    }
+
+    #[tokio::test]
+    async fn test_prefixed_question() {
+        let markdown = "Q: What is the\ncapital of France? #flashcard #geo\nA: Paris\nsince 508\n\nQ: Unanswered #flashcard\n\nQ: q #flashcard\nA:\na\n";
+        let parsed = parse_markdown(Cursor::new(markdown)).await.unwrap();
+        let cards: Vec<&Flashcard> = parsed.cards.iter().map(|(_, card)| card).collect();
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].question, "What is the\ncapital of France?");
+        assert_eq!(cards[0].answer, "Paris\nsince 508\n");
+        assert_eq!(cards[0].tags, vec!["geo"]);
+        assert_eq!(cards[1].question, "q");
+        assert_eq!(cards[1].answer, "a\n");
+        assert_eq!(
+            parsed.diagnostics,
+            vec![Diagnostic::error(
+                6,
+                15,
+                "Card has no answer, expected a line starting with A:"
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_single_line() {
+        let markdown = "Capital of France :: Paris #flashcard\n\nder Hund ::: the dog #flashcard ^d1\n---\nmap :: (a -> b) -> [a] -> [b]? #flashcard\nfunctor map\n---\nstd::vector :: a list #flashcard";
+        let reader = Cursor::new(markdown);
+        let flashcards = read_markdown(reader).await.unwrap();
+        assert_eq!(flashcards.len(), 5);
+        assert_eq!(flashcards[0].question, "Capital of France");
+        assert_eq!(flashcards[0].answer, "Paris");
+        assert_eq!(flashcards[1].question, "der Hund");
+        assert_eq!(flashcards[2].question, "the dog");
+        assert_eq!(flashcards[2].uid.as_deref(), Some("d1:r"));
+        // Followed by an answer, so it is not a single line card.
+        assert_eq!(flashcards[3].question, "map :: (a -> b) -> [a] -> [b]?");
+        assert_eq!(flashcards[3].answer, "functor map\n");
+        assert_eq!(flashcards[4].question, "std::vector");
+        assert_eq!(flashcards[4].answer, "a list");
+        let parsed = parse_markdown(Cursor::new(markdown)).await.unwrap();
+        assert!(parsed.diagnostics.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hash_in_question() {
+        let markdown = "#include <stdio.h> #c #flashcard\nincludes the header\n---\nWhat is C#? #flashcard\na language\n---\n";
+        let reader = Cursor::new(markdown);
+        let flashcards = read_markdown(reader).await.unwrap();
+        assert_eq!(flashcards[0].question, "#include <stdio.h>");
+        assert_eq!(flashcards[0].tags, vec!["c"]);
+        assert_eq!(flashcards[1].question, "What is C#?");
+    }
+
+    #[tokio::test]
+    async fn test_commonmark_blocks() {
+        let markdown = "---\ntitle: Notes\n---\nWhat is a YAML document? #flashcard\n```yaml\n---\na: 1\n```\n---\n`#flashcard` in code is text\n\n    #flashcard in an indented block\n\n## Heading #flashcard\n";
+        let reader = Cursor::new(markdown);
+        let parsed = parse_markdown(reader).await.unwrap();
+        assert!(parsed.diagnostics.is_empty());
+        let cards: Vec<&Flashcard> = parsed.cards.iter().map(|(_, card)| card).collect();
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].question, "What is a YAML document?");
+        assert_eq!(cards[0].answer, "```yaml\n---\na: 1\n```\n");
+        assert_eq!(parsed.cards[0].0, 4);
+    }
+
+    #[tokio::test]
+    async fn test_whitespace() {
+        let markdown = "How to loop in Rust? #flashcard\n\n```rust\nfor x in xs {\n    f(x);\n}\n```\n- a\n  - b\n\n---\nQ: What is this? #flashcard\nA:\n```python\ndef f():\n\n    pass\n```\n\n";
+        let reader = Cursor::new(markdown);
+        let flashcards = read_markdown(reader).await.unwrap();
+        assert_eq!(flashcards.len(), 2);
+        assert_eq!(
+            flashcards[0].answer,
+            "```rust\nfor x in xs {\n    f(x);\n}\n```\n- a\n  - b\n"
+        );
+        assert_eq!(flashcards[1].question, "What is this?");
+        assert_eq!(
+            flashcards[1].answer,
+            "```python\ndef f():\n\n    pass\n```\n"
+        );
+    }
+
+    #[test]
+    fn test_trim_blank_lines() {
+        assert_eq!(trim_blank_lines("\n  \n    code\n\n  \n"), "    code\n");
+        assert_eq!(trim_blank_lines("a\n\nb"), "a\n\nb");
+        assert_eq!(trim_blank_lines(" \n\n"), "");
+    }
+
+    #[tokio::test]
+    async fn test_front_matter() {
+        let markdown = "---\ndeck: lang::haskell\ntags: [fp]\nalgorithm: FSRS\nreverse: true\n---\nMonad #flashcard #FP #io\nA monoid\n---\n";
+        let reader = Cursor::new(markdown);
+        let parsed = parse_markdown(reader).await.unwrap();
+        assert!(parsed.diagnostics.is_empty());
+        assert_eq!(parsed.cards.len(), 2);
+        let (line, card) = &parsed.cards[1];
+        assert_eq!(*line, 7);
+        assert_eq!(card.question, "A monoid\n");
+        assert_eq!(card.tags, vec!["fp", "io"]);
+        assert_eq!(card.deck.as_deref(), Some("lang::haskell"));
+        assert_eq!(card.algorithm.as_deref(), Some("FSRS"));
+
+        let markdown = "---\ndisabled: true\n---\nq #flashcard\n";
+        let parsed = parse_markdown(Cursor::new(markdown)).await.unwrap();
+        assert!(parsed.cards.is_empty());
+        assert!(parsed.diagnostics.is_empty());
+
+        let markdown = "---\nalgorithm: Leitner\n---\nq #flashcard\na\n---\n";
+        let parsed = parse_markdown(Cursor::new(markdown)).await.unwrap();
+        assert_eq!(parsed.cards.len(), 1);
+        assert_eq!(parsed.cards[0].1.algorithm, None);
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert_eq!(parsed.diagnostics[0].line, 2);
+        assert_eq!(parsed.diagnostics[0].severity, Severity::Warning);
+    }
+
+    #[tokio::test]
+    async fn test_card_instead_of_front_matter() {
+        let markdown = "---\nq #flashcard\na\n---\n";
+        let parsed = parse_markdown(Cursor::new(markdown)).await.unwrap();
+        assert!(parsed.diagnostics.is_empty());
+        assert_eq!(parsed.cards.len(), 1);
+        assert_eq!(parsed.cards[0].1.question, "q");
+        assert_eq!(parsed.cards[0].1.answer, "a\n");
+    }
+
     #[tokio::test]
     async fn test_empty_question() {
         let markdown = "#flashcard\n---\n---\n";
@@ -206,4 +1046,35 @@ a normal answer
         let flashcards = read_markdown(reader).await;
         assert!(flashcards.is_err());
     }
+
+    #[tokio::test]
+    async fn test_error_line() {
+        let markdown = "text\nq #flashcard\na\n---\n\nq2 #flashcard\n---\n";
+        let reader = Cursor::new(markdown);
+        let err = read_markdown(reader).await.unwrap_err();
+        let err = err.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!(err.line, 6);
+        assert_eq!(err.column, 4);
+        assert_eq!(err.message, "Card cannot have empty answer text");
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics() {
+        let markdown =
+            "#flashcard\n---\n---\n\n #flashcard\nq\n---\na\n---\n#flashcard\n\nq\n---\na\n";
+        let reader = Cursor::new(markdown);
+        let parsed = parse_markdown(reader).await.unwrap();
+        assert_eq!(parsed.cards.len(), 2);
+        assert_eq!(
+            parsed.diagnostics,
+            vec![
+                Diagnostic::error(1, 1, "Card cannot have an empty question or answer text"),
+                Diagnostic::warning(
+                    10,
+                    1,
+                    "Card is not terminated by a horizontal rule, the rest of the file is its answer"
+                ),
+            ]
+        );
+    }
 }
@@ -1,2 +1,7 @@
+pub mod cloze;
+pub mod diagnostic;
+pub mod front_matter;
+pub mod lines;
 pub mod markdown;
 pub mod parser;
+pub mod writeback;
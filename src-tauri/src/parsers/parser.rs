@@ -1,37 +1,81 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+
 use anyhow::{Context, Result};
-use log::debug;
-use tokio::fs::File;
-use tokio::io::BufReader;
+use log::{debug, warn};
 use walkdir::WalkDir;
 
 use crate::models::flashcard::Flashcard;
-use crate::parsers::markdown::read_markdown;
+use crate::parsers::diagnostic::Diagnostic;
+use crate::parsers::markdown::{parse_markdown, read_markdown};
 
-pub async fn parse_folder(folder_path: &str) -> Result<Vec<Flashcard>> {
-    let mut cards_vec = vec![];
-    for entry in WalkDir::new(folder_path)
-        .follow_links(false)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
+/// Version of the parsing rules, stored with every synced file. Increase it
+/// whenever the same markdown produces different cards, so that all files
+/// are read again on the next sync.
+pub const PARSER_VERSION: i64 = 12;
+
+/// Returns paths of all markdown files in the folder. Fails if the folder
+/// itself cannot be read, so that its cards are not taken as removed.
+pub fn markdown_files(folder_path: &str) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for entry in WalkDir::new(folder_path).follow_links(false) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) if e.depth() == 0 => {
+                return Err(e).with_context(|| format!("Failed to read folder: {}", folder_path));
+            }
+            Err(e) => {
+                warn!("Skipping unreadable entry: {}", e);
+                continue;
+            }
+        };
         let fname = entry.file_name().to_string_lossy();
         debug!("visiting file {}", fname);
         if fname.ends_with(".md") {
-            let path = entry.path();
-            let path_stringified = path.to_string_lossy();
-            let file = File::open(path).await?;
-            let reader = BufReader::new(file);
-            let cards = read_markdown(reader)
-                .await
-                .with_context(|| format!("Failed to parse markdown: {}", path_stringified))?;
-            let cards: Vec<_> = cards
-                .into_iter()
-                .map(|c| c.with_path(folder_path.to_string(), path_stringified.to_string()))
-                .collect();
-            cards_vec.extend(cards);
+            paths.push(entry.into_path());
         } else {
             debug!("skipping file {}", fname);
         }
     }
-    Ok(cards_vec)
+    Ok(paths)
+}
+
+/// Parses the content of a markdown file from the synced folder.
+pub async fn parse_file(folder_path: &str, path: &str, content: &str) -> Result<Vec<Flashcard>> {
+    let cards = read_markdown(Cursor::new(content))
+        .await
+        .with_context(|| format!("Failed to parse markdown: {}", path))?;
+    Ok(cards
+        .into_iter()
+        .map(|c| c.with_path(folder_path.to_string(), path.to_string()))
+        .collect())
+}
+
+/// Checks all markdown files in the folder, returns the problems found.
+pub async fn lint_folder(folder_path: &str) -> Vec<Diagnostic> {
+    let paths = match markdown_files(folder_path) {
+        Ok(paths) => paths,
+        Err(e) => return vec![Diagnostic::from_error(folder_path, e)],
+    };
+    let mut diagnostics = vec![];
+    for path in paths {
+        let path_str = path.to_string_lossy();
+        let parsed = match tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read: {}", path_str))
+        {
+            Ok(content) => parse_markdown(Cursor::new(content)).await,
+            Err(e) => Err(e),
+        };
+        match parsed {
+            Ok(parsed) => diagnostics.extend(
+                parsed
+                    .diagnostics
+                    .into_iter()
+                    .map(|diagnostic| diagnostic.in_file(&path_str)),
+            ),
+            Err(e) => diagnostics.push(Diagnostic::from_error(&path_str, e)),
+        }
+    }
+    diagnostics
 }
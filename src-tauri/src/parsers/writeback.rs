@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use rand::Rng;
+
+use crate::models::flashcard::Flashcard;
+use crate::parsers::diagnostic::Diagnostic;
+use crate::parsers::markdown::{parse_markdown, CARD_ID_SEPARATOR};
+use crate::parsers::parser::markdown_files;
+
+const ID_LENGTH: usize = 6;
+const ID_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Generates a random card ID which is not in the `used` set.
+fn new_card_id(used: &HashSet<String>) -> String {
+    let mut rng = rand::thread_rng();
+    loop {
+        let id: String = (0..ID_LENGTH)
+            .map(|_| ID_CHARS[rng.gen_range(0..ID_CHARS.len())] as char)
+            .collect();
+        if !used.contains(&id) {
+            return id;
+        }
+    }
+}
+
+/// Appends an ID marker (`^id`) to the `#flashcard` line of every card which
+/// does not have one. Invalid cards are left without an ID, the rest of the
+/// content is kept byte-for-byte.
+/// Returns the new content or None if all cards already have an ID.
+/// IDs from `used` are not generated, newly generated IDs are added to it.
+pub async fn insert_card_ids(content: &str, used: &mut HashSet<String>) -> Result<Option<String>> {
+    let cards = parse_markdown(Cursor::new(content)).await?.cards;
+    let missing: HashSet<usize> = cards
+        .iter()
+        .filter(|(_, card)| card.uid.is_none())
+        .map(|(line, _)| *line)
+        .collect();
+    if missing.is_empty() {
+        return Ok(None);
+    }
+
+    let mut result = String::with_capacity(content.len() + missing.len() * (ID_LENGTH + 2));
+    for (idx, line) in content.split_inclusive('\n').enumerate() {
+        if !missing.contains(&(idx + 1)) {
+            result.push_str(line);
+            continue;
+        }
+        let text = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[text.len()..];
+        let id = new_card_id(used);
+        result.push_str(text);
+        if !text.ends_with(char::is_whitespace) {
+            result.push(' ');
+        }
+        result.push('^');
+        result.push_str(&id);
+        result.push_str(ending);
+        used.insert(id);
+    }
+    Ok(Some(result))
+}
+
+/// Result of [`write_card_ids`].
+#[derive(Debug, Default)]
+pub struct WrittenIds {
+    /// Number of modified files.
+    pub modified: usize,
+    /// The files which could not be read or written, they are skipped.
+    pub errors: Vec<Diagnostic>,
+}
+
+async fn read_cards(path: &Path) -> Result<(String, Vec<(usize, Flashcard)>)> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read: {}", path.display()))?;
+    let cards = parse_markdown(Cursor::new(&content))
+        .await
+        .with_context(|| format!("Failed to parse markdown: {}", path.display()))?
+        .cards;
+    Ok((content, cards))
+}
+
+async fn write_ids(path: &Path, content: &str, used: &mut HashSet<String>) -> Result<bool> {
+    let Some(new_content) = insert_card_ids(content, used).await? else {
+        return Ok(false);
+    };
+    debug!("writing card IDs to {}", path.display());
+    tokio::fs::write(path, new_content)
+        .await
+        .with_context(|| format!("Failed to write: {}", path.display()))?;
+    Ok(true)
+}
+
+/// Inserts card IDs into all markdown files in the folder. Files which
+/// cannot be read or written are reported and skipped.
+pub async fn write_card_ids(folder_path: &str) -> WrittenIds {
+    let mut written = WrittenIds::default();
+    let paths = match markdown_files(folder_path) {
+        Ok(paths) => paths,
+        Err(e) => {
+            written.errors.push(Diagnostic::from_error(folder_path, e));
+            return written;
+        }
+    };
+
+    let mut contents = vec![];
+    let mut used = HashSet::new();
+    for path in paths {
+        let (content, cards) = match read_cards(&path).await {
+            Ok(read) => read,
+            Err(e) => {
+                let path = path.to_string_lossy();
+                warn!("Not writing card IDs to {}: {:#}", path, e);
+                written.errors.push(Diagnostic::from_error(&path, e));
+                continue;
+            }
+        };
+        for (_, card) in cards {
+            // Cards of the same note have the written ID with a suffix.
+            used.extend(
+                card.uid
+                    .and_then(|uid| uid.split(CARD_ID_SEPARATOR).next().map(String::from)),
+            );
+        }
+        contents.push((path, content));
+    }
+
+    for (path, content) in contents {
+        match write_ids(&path, &content, &mut used).await {
+            Ok(modified) => written.modified += modified as usize,
+            Err(e) => {
+                let path = path.to_string_lossy();
+                warn!("Not writing card IDs to {}: {:#}", path, e);
+                written.errors.push(Diagnostic::from_error(&path, e));
+            }
+        }
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::markdown::read_markdown;
+
+    #[tokio::test]
+    async fn insert_ids() {
+        let markdown = "Some text #tag\r\nquestion #flashcard\r\nanswer #flashcard\r\n---\r\n#flashcard #tag ^abc\nq\n---\na\n---\n#flashcard  \nq2\n---\na2";
+        let mut used = HashSet::from(["abc".to_string()]);
+        let result = insert_card_ids(markdown, &mut used).await.unwrap().unwrap();
+        assert_eq!(used.len(), 3);
+
+        let lines: Vec<&str> = result.split_inclusive('\n').collect();
+        let original: Vec<&str> = markdown.split_inclusive('\n').collect();
+        assert_eq!(lines.len(), original.len());
+        for (idx, (line, original)) in lines.iter().zip(&original).enumerate() {
+            if idx == 1 || idx == 9 {
+                assert_ne!(line, original);
+            } else {
+                assert_eq!(line, original);
+            }
+        }
+        assert!(lines[1].starts_with("question #flashcard ^"));
+        assert!(lines[1].ends_with("\r\n"));
+        assert!(lines[9].starts_with("#flashcard  ^"));
+
+        let cards = read_markdown(Cursor::new(&result)).await.unwrap();
+        assert_eq!(cards.len(), 3);
+        assert!(cards.iter().all(|c| c.uid.is_some()));
+        assert_eq!(cards[1].uid.as_deref(), Some("abc"));
+    }
+
+    #[tokio::test]
+    async fn nothing_to_insert() {
+        let markdown = "question #flashcard ^a1\nanswer\n---\nno cards here\n";
+        let result = insert_card_ids(markdown, &mut HashSet::new())
+            .await
+            .unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[tokio::test]
+    async fn skip_invalid_cards() {
+        let markdown = "q1 #flashcard\na1\n---\nq2 #flashcard\n";
+        let result = insert_card_ids(markdown, &mut HashSet::new())
+            .await
+            .unwrap()
+            .unwrap();
+        let lines: Vec<&str> = result.lines().collect();
+        assert!(lines[0].starts_with("q1 #flashcard ^"));
+        assert_eq!(lines[3], "q2 #flashcard");
+    }
+
+    #[test]
+    fn unique_ids() {
+        let mut used = HashSet::new();
+        for _ in 0..1000 {
+            let id = new_card_id(&used);
+            assert_eq!(id.len(), ID_LENGTH);
+            assert!(used.insert(id));
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::db::Db;
+use crate::models::prelude::Flashcard;
+use crate::parsers::diagnostic::Diagnostic;
+use crate::sync::{is_changed, plan_folder_sync, AmbiguousMatch, CardType};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "line")]
+pub enum DiffLine {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+/// An existing card which would be changed by the sync.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CardChange {
+    pub old: Flashcard,
+    pub new: Flashcard,
+    /// Distance of the questions if the card was matched by a similar
+    /// question and not by its ID or the same question.
+    pub distance: Option<usize>,
+    pub question_diff: Vec<DiffLine>,
+    pub answer_diff: Vec<DiffLine>,
+}
+
+/// What a sync of the folder would do, nothing is written into the database.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncPreview {
+    pub new: Vec<Flashcard>,
+    pub updated: Vec<CardChange>,
+    /// Cards which would be archived, because their text was removed.
+    pub removed: Vec<Flashcard>,
+    /// Number of matched cards which would stay the same.
+    pub unchanged: usize,
+    pub unchanged_files: usize,
+    pub ambiguous: Vec<AmbiguousMatch>,
+    /// Files which could not be parsed, their cards would be kept.
+    pub skipped_files: Vec<Diagnostic>,
+}
+
+/// Line diff of the two texts, based on the longest common subsequence.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    // lcs[i][j] is the length of the common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            diff.push(DiffLine::Same(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push(DiffLine::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+    diff
+}
+
+/// Computes what [`crate::sync::sync_folder`] would do with the folder.
+/// Card IDs are not written into the files.
+pub async fn preview_sync(db: &Db, folder: &str) -> Result<SyncPreview> {
+    let plan = plan_folder_sync(db, folder).await?;
+    let old_cards: HashMap<Option<i32>, &Flashcard> =
+        plan.old_cards.iter().map(|card| (card.id, card)).collect();
+    let distances: HashMap<usize, usize> = plan.report.similar.iter().copied().collect();
+
+    let mut preview = SyncPreview {
+        unchanged_files: plan.unchanged.len(),
+        ambiguous: plan.report.ambiguous,
+        skipped_files: plan.errors,
+        ..Default::default()
+    };
+    for (idx, card) in plan.report.cards.into_iter().enumerate() {
+        match card {
+            CardType::New(card) => preview.new.push(card),
+            CardType::Removed(card) => preview.removed.push(card),
+            CardType::Old(card) => {
+                let Some(&old) = old_cards.get(&card.id) else {
+                    continue;
+                };
+                let distance = distances.get(&idx).copied();
+                let restored = card.id.is_some_and(|id| plan.restored.contains(&id));
+                if distance.is_none() && !restored && !is_changed(old, &card) {
+                    preview.unchanged += 1;
+                    continue;
+                }
+                preview.updated.push(CardChange {
+                    old: old.clone(),
+                    distance,
+                    question_diff: diff_lines(&old.question, &card.question),
+                    answer_diff: diff_lines(&old.answer, &card.answer),
+                    new: card,
+                });
+            }
+        }
+    }
+    Ok(preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DiffLine::*;
+
+    #[test]
+    fn diff() {
+        assert_eq!(
+            diff_lines("a\nb\nc\n", "a\nx\nc\nd\n"),
+            vec![
+                Same("a".to_string()),
+                Removed("b".to_string()),
+                Added("x".to_string()),
+                Same("c".to_string()),
+                Added("d".to_string()),
+            ]
+        );
+        assert_eq!(diff_lines("", "a"), vec![Added("a".to_string())]);
+        assert_eq!(diff_lines("a", ""), vec![Removed("a".to_string())]);
+        assert_eq!(diff_lines("", ""), vec![]);
+    }
+}
@@ -0,0 +1,293 @@
+pub mod optimizer;
+
+use log::debug;
+
+use crate::models::answer::Answer;
+use crate::repetition_algs::RepetitionAlgorithm;
+
+/// Default FSRS v4 weights, fitted by the FSRS authors on a large
+/// collection of Anki reviews.
+pub const DEFAULT_WEIGHTS: [f64; 17] = [
+    0.4, 0.6, 2.4, 5.8, 4.93, 0.94, 0.86, 0.01, 1.49, 0.14, 0.94, 2.18, 0.05, 0.34, 1.26, 0.29,
+    2.61,
+];
+
+/// The lower and upper bound for every weight. Weights outside of these
+/// bounds produce nonsensical (or non-finite) memory states.
+pub const WEIGHT_BOUNDS: [(f64, f64); 17] = [
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (1.0, 10.0),
+    (0.1, 5.0),
+    (0.1, 5.0),
+    (0.0, 0.5),
+    (0.0, 3.0),
+    (0.1, 0.8),
+    (0.01, 2.5),
+    (0.5, 5.0),
+    (0.01, 0.2),
+    (0.01, 0.9),
+    (0.01, 2.0),
+    (0.0, 1.0),
+    (1.0, 4.0),
+];
+
+const SECONDS_IN_DAY: f64 = 86400.0;
+
+/// The four FSRS grades. The 0-100 answer rating is mapped onto them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grade {
+    Again = 1,
+    Hard = 2,
+    Good = 3,
+    Easy = 4,
+}
+
+impl Grade {
+    /// Converts the rating of an answer. Like the other algorithms, ratings
+    /// below 50 are failures, so "Worst" (0) and "Bad" (33) are Again.
+    /// "Decent" (66) is Hard and "Good" (100) is Good, Easy is not given,
+    /// as the buttons do not tell an easy answer from a good one. Other
+    /// ratings get the grade of the closest button.
+    pub fn from_rating(answer_rating: i32) -> Self {
+        match answer_rating {
+            r if r < 50 => Grade::Again,
+            r if r < 83 => Grade::Hard,
+            _ => Grade::Good,
+        }
+    }
+
+    fn value(self) -> f64 {
+        self as i32 as f64
+    }
+}
+
+/// The memory state of one card after a sequence of answers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryState {
+    /// Number of days after which the probability of recall drops to 90%.
+    pub stability: f64,
+    /// How hard the card is to remember, between 1 and 10.
+    pub difficulty: f64,
+}
+
+/// Probability of recalling a card with given stability after `elapsed_days`.
+pub fn retrievability(elapsed_days: f64, stability: f64) -> f64 {
+    (1.0 + elapsed_days / (9.0 * stability)).powi(-1)
+}
+
+fn initial_difficulty(w: &[f64; 17], grade: Grade) -> f64 {
+    (w[4] - (grade.value() - 3.0) * w[5]).clamp(1.0, 10.0)
+}
+
+fn next_difficulty(w: &[f64; 17], difficulty: f64, grade: Grade) -> f64 {
+    let difficulty = difficulty - w[6] * (grade.value() - 3.0);
+    // Mean reversion towards the difficulty of a card answered as 'Good'.
+    (w[7] * initial_difficulty(w, Grade::Good) + (1.0 - w[7]) * difficulty).clamp(1.0, 10.0)
+}
+
+fn recall_stability(w: &[f64; 17], state: MemoryState, r: f64, grade: Grade) -> f64 {
+    let hard_penalty = if grade == Grade::Hard { w[15] } else { 1.0 };
+    let easy_bonus = if grade == Grade::Easy { w[16] } else { 1.0 };
+    state.stability
+        * (1.0
+            + w[8].exp()
+                * (11.0 - state.difficulty)
+                * state.stability.powf(-w[9])
+                * ((w[10] * (1.0 - r)).exp() - 1.0)
+                * hard_penalty
+                * easy_bonus)
+}
+
+fn forget_stability(w: &[f64; 17], state: MemoryState, r: f64) -> f64 {
+    let stability = w[11]
+        * state.difficulty.powf(-w[12])
+        * ((state.stability + 1.0).powf(w[13]) - 1.0)
+        * (w[14] * (1.0 - r)).exp();
+    stability.min(state.stability)
+}
+
+fn days_between(from: &Answer, to: &Answer) -> f64 {
+    (to.timestamp - from.timestamp).num_seconds().max(0) as f64 / SECONDS_IN_DAY
+}
+
+/// Replays the answers with the given weights, calling `on_review` before
+/// each answer except the first one with the predicted probability of
+/// recall and the grade that was actually given. Returns the final state.
+pub(crate) fn replay(
+    w: &[f64; 17],
+    answers: &[Answer],
+    mut on_review: impl FnMut(f64, Grade),
+) -> Option<MemoryState> {
+    let (first, rest) = answers.split_first()?;
+    let grade = Grade::from_rating(first.answer_rating);
+    let mut state = MemoryState {
+        stability: w[grade as usize - 1],
+        difficulty: initial_difficulty(w, grade),
+    };
+    let mut previous = first;
+    for answer in rest {
+        let grade = Grade::from_rating(answer.answer_rating);
+        let r = retrievability(days_between(previous, answer), state.stability);
+        on_review(r, grade);
+        let stability = match grade {
+            Grade::Again => forget_stability(w, state, r),
+            _ => recall_stability(w, state, r, grade),
+        };
+        state = MemoryState {
+            stability: stability.max(0.01),
+            difficulty: next_difficulty(w, state.difficulty, grade),
+        };
+        previous = answer;
+    }
+    Some(state)
+}
+
+/// The Free Spaced Repetition Scheduler.
+/// Models the stability and difficulty of every card from its answer
+/// history and schedules the next repetition when the predicted
+/// probability of recall drops to the desired retention.
+/// See <https://github.com/open-spaced-repetition/fsrs4anki/wiki/The-Algorithm>.
+pub struct FsrsRA {
+    weights: [f64; 17],
+    /// The probability of recall at which the card should be repeated.
+    desired_retention: f64,
+    /// A maximum day limit which overrides the computed interval.
+    limit: i32,
+}
+
+impl FsrsRA {
+    pub fn new(weights: [f64; 17], desired_retention: f64, limit: i32) -> Self {
+        Self {
+            weights,
+            desired_retention,
+            limit,
+        }
+    }
+
+    pub fn memory_state(&self, answers: &[Answer]) -> Option<MemoryState> {
+        replay(&self.weights, answers, |_, _| {})
+    }
+
+    /// Returns the number of days that should pass after the last answer
+    /// before the question is repeated.
+    pub fn interval(&self, answers: &[Answer]) -> i32 {
+        match self.memory_state(answers) {
+            None => 0,
+            Some(state) => {
+                let interval = 9.0 * state.stability * (1.0 / self.desired_retention - 1.0);
+                (interval.round() as i32).clamp(1, self.limit)
+            }
+        }
+    }
+}
+
+impl Default for FsrsRA {
+    fn default() -> Self {
+        Self::new(DEFAULT_WEIGHTS, 0.9, 36500)
+    }
+}
+
+impl RepetitionAlgorithm for FsrsRA {
+    fn repeat_question(&self, answers: &[Answer]) -> bool {
+        match answers.last() {
+            None => true,
+            Some(last) => {
+                if Grade::from_rating(last.answer_rating) == Grade::Again {
+                    return true;
+                }
+                let interval = self.interval(answers);
+                let now = chrono::Utc::now().naive_utc();
+                let days_between = now.signed_duration_since(last.timestamp).num_days();
+                debug!("days_between >= interval: {} >= {}", days_between, interval);
+                days_between >= interval as i64
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(rating: i32, days_ago: u64) -> Answer {
+        Answer {
+            id: None,
+            flashcard_id: 1,
+            answer_rating: rating,
+            timestamp: chrono::Utc::now()
+                .naive_utc()
+                .checked_sub_days(chrono::Days::new(days_ago))
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn no_questions() {
+        assert!(FsrsRA::default().repeat_question(&[]));
+    }
+
+    #[test]
+    fn grades() {
+        assert_eq!(Grade::from_rating(0), Grade::Again);
+        assert_eq!(Grade::from_rating(33), Grade::Again);
+        assert_eq!(Grade::from_rating(66), Grade::Hard);
+        assert_eq!(Grade::from_rating(100), Grade::Good);
+        assert_eq!(Grade::from_rating(10), Grade::Again);
+        assert_eq!(Grade::from_rating(49), Grade::Again);
+        assert_eq!(Grade::from_rating(50), Grade::Hard);
+        assert_eq!(Grade::from_rating(80), Grade::Hard);
+        assert_eq!(Grade::from_rating(90), Grade::Good);
+    }
+
+    #[test]
+    fn first_answer_uses_initial_stability() {
+        let fsrs = FsrsRA::default();
+        let state = fsrs.memory_state(&[answer(100, 0)]).unwrap();
+        assert_eq!(state.stability, DEFAULT_WEIGHTS[2]);
+        assert_eq!(state.difficulty, DEFAULT_WEIGHTS[4]);
+    }
+
+    #[test]
+    fn intervals_grow_with_correct_answers() {
+        let fsrs = FsrsRA::default();
+        let short = [answer(80, 10), answer(80, 8)];
+        let long = [answer(80, 30), answer(80, 25), answer(80, 15)];
+        assert!(fsrs.interval(&short) < fsrs.interval(&long));
+    }
+
+    #[test]
+    fn forgetting_lowers_stability() {
+        let fsrs = FsrsRA::default();
+        let good = fsrs
+            .memory_state(&[answer(80, 30), answer(80, 20), answer(80, 5)])
+            .unwrap();
+        let forgot = fsrs
+            .memory_state(&[answer(80, 30), answer(80, 20), answer(0, 5)])
+            .unwrap();
+        assert!(forgot.stability < good.stability);
+        assert!(forgot.difficulty > good.difficulty);
+    }
+
+    #[test]
+    fn last_incorrect() {
+        let fsrs = FsrsRA::default();
+        assert!(fsrs.repeat_question(&[answer(100, 10), answer(0, 0)]));
+    }
+
+    #[test]
+    fn dont_repeat_question() {
+        let fsrs = FsrsRA::default();
+        assert!(!fsrs.repeat_question(&[answer(100, 20), answer(100, 1)]));
+    }
+
+    #[test]
+    fn interval_over_limit() {
+        let fsrs = FsrsRA::new(DEFAULT_WEIGHTS, 0.9, 3);
+        let answers = [answer(100, 20), answer(100, 3)];
+        assert_eq!(fsrs.interval(&answers), 3);
+        assert!(fsrs.repeat_question(&answers));
+    }
+}
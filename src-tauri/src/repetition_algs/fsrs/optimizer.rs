@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+
+use crate::models::answer::Answer;
+use crate::repetition_algs::fsrs::{replay, Grade, DEFAULT_WEIGHTS, WEIGHT_BOUNDS};
+
+const ITERATIONS: usize = 200;
+const LEARNING_RATE: f64 = 0.02;
+const EPSILON: f64 = 1e-4;
+
+/// Groups answers by the flashcard they belong to. Answers of each card are
+/// sorted by timestamp, with the most recent answer being the last one.
+pub fn group_by_card(answers: Vec<Answer>) -> Vec<Vec<Answer>> {
+    let mut histories: BTreeMap<i32, Vec<Answer>> = BTreeMap::new();
+    for answer in answers {
+        histories
+            .entry(answer.flashcard_id)
+            .or_default()
+            .push(answer);
+    }
+    histories
+        .into_values()
+        .map(|mut history| {
+            history.sort_by_key(|a| a.timestamp);
+            history
+        })
+        .collect()
+}
+
+/// The average binary cross entropy between the predicted probability of
+/// recall and whether the card was actually recalled, over all reviews.
+pub fn loss(weights: &[f64; 17], histories: &[Vec<Answer>]) -> f64 {
+    let mut total = 0.0;
+    let mut count = 0;
+    for history in histories {
+        replay(weights, history, |r, grade| {
+            let r = r.clamp(1e-6, 1.0 - 1e-6);
+            let recalled = grade != Grade::Again;
+            total -= if recalled { r.ln() } else { (1.0 - r).ln() };
+            count += 1;
+        });
+    }
+    if count == 0 {
+        0.0
+    } else {
+        total / count as f64
+    }
+}
+
+fn clamp_to_bounds(weights: &mut [f64; 17]) {
+    for (w, (low, high)) in weights.iter_mut().zip(WEIGHT_BOUNDS) {
+        *w = w.clamp(low, high);
+    }
+}
+
+/// Fits the FSRS weights to the given review histories by minimizing
+/// the [`loss`] with the Adam optimizer, starting from [`DEFAULT_WEIGHTS`].
+/// The gradient is estimated numerically, which is slow but good enough
+/// for offline fitting on a personal collection.
+pub fn optimize(histories: &[Vec<Answer>]) -> [f64; 17] {
+    let mut weights = DEFAULT_WEIGHTS;
+    if histories.iter().all(|h| h.len() < 2) {
+        return weights;
+    }
+
+    let (beta1, beta2) = (0.9, 0.999);
+    let mut m = [0.0; 17];
+    let mut v = [0.0; 17];
+    let mut best = (loss(&weights, histories), weights);
+    for t in 1..=ITERATIONS {
+        for i in 0..weights.len() {
+            let mut plus = weights;
+            let mut minus = weights;
+            plus[i] += EPSILON;
+            minus[i] -= EPSILON;
+            let gradient = (loss(&plus, histories) - loss(&minus, histories)) / (2.0 * EPSILON);
+
+            m[i] = beta1 * m[i] + (1.0 - beta1) * gradient;
+            v[i] = beta2 * v[i] + (1.0 - beta2) * gradient * gradient;
+            let m_hat = m[i] / (1.0 - beta1.powi(t as i32));
+            let v_hat = v[i] / (1.0 - beta2.powi(t as i32));
+            weights[i] -= LEARNING_RATE * m_hat / (v_hat.sqrt() + 1e-8);
+        }
+        clamp_to_bounds(&mut weights);
+        let current = loss(&weights, histories);
+        if current < best.0 {
+            best = (current, weights);
+        }
+    }
+    best.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(flashcard_id: i32, rating: i32, day: i64) -> Answer {
+        Answer {
+            id: None,
+            flashcard_id,
+            answer_rating: rating,
+            timestamp: chrono::NaiveDate::from_ymd_opt(2023, 1, 1)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+                + chrono::Duration::days(day),
+        }
+    }
+
+    #[test]
+    fn group_answers() {
+        let answers = vec![answer(2, 100, 3), answer(1, 100, 1), answer(2, 0, 1)];
+        let grouped = group_by_card(answers);
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped[0], vec![answer(1, 100, 1)]);
+        assert_eq!(grouped[1], vec![answer(2, 0, 1), answer(2, 100, 3)]);
+    }
+
+    #[test]
+    fn no_history_keeps_defaults() {
+        assert_eq!(optimize(&[]), DEFAULT_WEIGHTS);
+        assert_eq!(optimize(&[vec![answer(1, 100, 0)]]), DEFAULT_WEIGHTS);
+    }
+
+    #[test]
+    fn fitting_lowers_loss() {
+        // A learner with a great memory, who remembers everything even
+        // after long pauses, except for the cards seen for the first time.
+        let histories: Vec<Vec<Answer>> = (0..20)
+            .map(|id| {
+                vec![
+                    answer(id, 80, 0),
+                    answer(id, 80, 10 + id as i64),
+                    answer(id, 80, 60 + 2 * id as i64),
+                    answer(id, 80, 200 + 5 * id as i64),
+                ]
+            })
+            .collect();
+        let fitted = optimize(&histories);
+        assert!(loss(&fitted, &histories) < loss(&DEFAULT_WEIGHTS, &histories));
+        for (w, (low, high)) in fitted.iter().zip(WEIGHT_BOUNDS) {
+            assert!((low..=high).contains(w));
+        }
+    }
+}
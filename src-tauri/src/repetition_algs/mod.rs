@@ -1,4 +1,7 @@
+pub mod fsrs;
 mod naive_exponential;
+pub mod registry;
+mod super_memo2;
 
 use crate::models::answer::Answer;
 
@@ -11,6 +14,9 @@ pub trait RepetitionAlgorithm {
 }
 
 pub mod prelude {
+    pub use super::fsrs::FsrsRA;
     pub use super::naive_exponential::NaiveExponentialRA;
+    pub use super::registry::AlgorithmConfig;
+    pub use super::super_memo2::SuperMemo2RA;
     pub use super::RepetitionAlgorithm;
 }
@@ -4,11 +4,13 @@ use crate::models::answer::Answer;
 use crate::repetition_algs::RepetitionAlgorithm;
 
 /// A naive exponential spaced repetition algorithm.
-/// The questions are repeated after 2^N days, where N is the number of
-/// consequent good questions. It does not take into account the
+/// The questions are repeated after start * 2^(N-1) days, where N is the
+/// number of consequent good questions. It does not take into account the
 /// previous results, only the last N consequent good answers.
 pub struct NaiveExponentialRA {
     minimum_rating: i32,
+    /// The number of days before repeating a question answered correctly once.
+    start: i32,
     /// A maximum day limit which overrides the normal exponential procedure.
     /// If the number of days between the last answer and now is greater than
     /// this, then the question will always be repeated.
@@ -16,9 +18,10 @@ pub struct NaiveExponentialRA {
 }
 
 impl NaiveExponentialRA {
-    pub fn new(minimum_rating: i32, limit: i32) -> Self {
+    pub fn new(minimum_rating: i32, start: i32, limit: i32) -> Self {
         Self {
             minimum_rating,
+            start,
             limit,
         }
     }
@@ -42,8 +45,10 @@ impl RepetitionAlgorithm for NaiveExponentialRA {
                             Some(idx) => idx,
                             None => len,
                         };
-                    // The questions are repeated after 2^N days, where N is the number of consequent good questions
-                    let day_limit = 2i32.pow(good_questions as u32 - 1);
+                    // The questions are repeated after start * 2^(N-1) days, where N is the number of consequent good questions
+                    let day_limit = 2i32
+                        .saturating_pow(good_questions as u32 - 1)
+                        .saturating_mul(self.start);
                     if day_limit >= self.limit {
                         return true;
                     }
@@ -71,6 +76,7 @@ mod tests {
         // Last incorrect always leads to repetition.
         let spa = NaiveExponentialRA {
             minimum_rating: 50,
+            start: 1,
             limit: 128,
         };
         let answers = vec![
@@ -109,6 +115,7 @@ mod tests {
     fn repeat_one() {
         let spa = NaiveExponentialRA {
             minimum_rating: 50,
+            start: 1,
             limit: 128,
         };
         let answers = vec![Answer {
@@ -123,10 +130,29 @@ mod tests {
         assert!(spa.repeat_question(&answers));
     }
 
+    #[test]
+    fn start_interval() {
+        let spa = NaiveExponentialRA::new(50, 2, 128);
+        let answered = |days_ago| {
+            vec![Answer {
+                id: None,
+                flashcard_id: 1,
+                answer_rating: RIGHT,
+                timestamp: chrono::Utc::now()
+                    .naive_utc()
+                    .checked_sub_days(chrono::Days::new(days_ago))
+                    .unwrap(),
+            }]
+        };
+        assert!(!spa.repeat_question(&answered(1)));
+        assert!(spa.repeat_question(&answered(2)));
+    }
+
     #[test]
     fn repeat_question() {
         let spa = NaiveExponentialRA {
             minimum_rating: 50,
+            start: 1,
             limit: 128,
         };
         let answers = vec![
@@ -165,6 +191,7 @@ mod tests {
     fn dont_repeat_question() {
         let spa = NaiveExponentialRA {
             minimum_rating: 50,
+            start: 1,
             limit: 128,
         };
         let answers = vec![
@@ -203,6 +230,7 @@ mod tests {
     fn last_over_limit() {
         let spa = NaiveExponentialRA {
             minimum_rating: 50,
+            start: 1,
             limit: 8,
         };
         let answers = vec![
@@ -241,6 +269,7 @@ mod tests {
     fn no_questions() {
         let spa = NaiveExponentialRA {
             minimum_rating: 50,
+            start: 1,
             limit: 128,
         };
         let answers = vec![];
@@ -0,0 +1,249 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::repetition_algs::fsrs::DEFAULT_WEIGHTS;
+use crate::repetition_algs::prelude::*;
+
+/// The repetition algorithm selected in the app settings, together with
+/// its parameters. The name matches the algorithm names of the frontend.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "name")]
+pub enum AlgorithmConfig {
+    #[serde(rename = "Naive Exponential")]
+    NaiveExponential {
+        /// The number of days before the first repetition, doubled after
+        /// every correct answer.
+        #[serde(default = "default_start")]
+        start: i32,
+        /// The maximum number of days to wait before repeating a question.
+        cap: i32,
+    },
+    #[serde(rename = "SuperMemo 2")]
+    SuperMemo2 { cap: i32 },
+    #[serde(rename = "FSRS", rename_all = "camelCase")]
+    Fsrs {
+        cap: i32,
+        #[serde(default = "default_desired_retention")]
+        desired_retention: f64,
+        #[serde(default = "default_weights")]
+        weights: [f64; 17],
+    },
+}
+
+fn default_start() -> i32 {
+    2
+}
+
+fn default_desired_retention() -> f64 {
+    0.9
+}
+
+fn default_weights() -> [f64; 17] {
+    DEFAULT_WEIGHTS
+}
+
+impl Default for AlgorithmConfig {
+    fn default() -> Self {
+        AlgorithmConfig::NaiveExponential {
+            start: default_start(),
+            cap: 128,
+        }
+    }
+}
+
+/// The names of the algorithms, as written in the config.
+pub const ALGORITHM_NAMES: [&str; 3] = ["Naive Exponential", "SuperMemo 2", "FSRS"];
+
+impl AlgorithmConfig {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlgorithmConfig::NaiveExponential { .. } => ALGORITHM_NAMES[0],
+            AlgorithmConfig::SuperMemo2 { .. } => ALGORITHM_NAMES[1],
+            AlgorithmConfig::Fsrs { .. } => ALGORITHM_NAMES[2],
+        }
+    }
+
+    fn cap(&self) -> i32 {
+        match *self {
+            AlgorithmConfig::NaiveExponential { cap, .. }
+            | AlgorithmConfig::SuperMemo2 { cap }
+            | AlgorithmConfig::Fsrs { cap, .. } => cap,
+        }
+    }
+
+    /// Returns the algorithm with the name, ignoring case. It is this one if
+    /// the name is the same, otherwise it has the day cap of this one and the
+    /// default parameters. None if there is no such algorithm.
+    pub fn with_name(&self, name: &str) -> Option<AlgorithmConfig> {
+        if self.name().eq_ignore_ascii_case(name) {
+            return Some(self.clone());
+        }
+        let cap = self.cap();
+        let defaults = [
+            AlgorithmConfig::NaiveExponential {
+                start: default_start(),
+                cap,
+            },
+            AlgorithmConfig::SuperMemo2 { cap },
+            AlgorithmConfig::Fsrs {
+                cap,
+                desired_retention: default_desired_retention(),
+                weights: default_weights(),
+            },
+        ];
+        defaults
+            .into_iter()
+            .find(|config| config.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the FSRS algorithm with the fitted weights. Fails if FSRS is
+    /// not the configured algorithm.
+    pub fn with_fsrs_weights(&self, weights: [f64; 17]) -> Result<AlgorithmConfig> {
+        match self {
+            AlgorithmConfig::Fsrs {
+                cap,
+                desired_retention,
+                ..
+            } => Ok(AlgorithmConfig::Fsrs {
+                cap: *cap,
+                desired_retention: *desired_retention,
+                weights,
+            }),
+            _ => bail!(
+                "The weights can only be set for FSRS, the algorithm is {}",
+                self.name()
+            ),
+        }
+    }
+
+    /// Instantiates the configured algorithm.
+    pub fn build(&self) -> Result<Box<dyn RepetitionAlgorithm + Send + Sync>> {
+        match *self {
+            AlgorithmConfig::NaiveExponential { cap, .. }
+            | AlgorithmConfig::SuperMemo2 { cap }
+            | AlgorithmConfig::Fsrs { cap, .. }
+                if cap <= 0 =>
+            {
+                bail!("The day cap must be a positive number, got {}", cap)
+            }
+            AlgorithmConfig::NaiveExponential { start, .. } if start <= 0 => {
+                bail!(
+                    "The first interval must be a positive number, got {}",
+                    start
+                )
+            }
+            AlgorithmConfig::NaiveExponential { start, cap } => {
+                Ok(Box::new(NaiveExponentialRA::new(50, start, cap)))
+            }
+            AlgorithmConfig::SuperMemo2 { cap } => Ok(Box::new(SuperMemo2RA::new(3, cap))),
+            AlgorithmConfig::Fsrs {
+                cap,
+                desired_retention,
+                weights,
+            } => {
+                if !(desired_retention > 0.0 && desired_retention < 1.0) {
+                    bail!(
+                        "Desired retention must be between 0 and 1, got {}",
+                        desired_retention
+                    );
+                }
+                Ok(Box::new(FsrsRA::new(weights, desired_retention, cap)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_frontend_config() {
+        let json = r#"{
+            "name": "Naive Exponential",
+            "description": "Repeats questions every x days.",
+            "start": 2,
+            "cap": 128
+        }"#;
+        let config: AlgorithmConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            config,
+            AlgorithmConfig::NaiveExponential { start: 2, cap: 128 }
+        );
+        assert!(config.build().is_ok());
+    }
+
+    #[test]
+    fn deserialize_fsrs_defaults() {
+        let config: AlgorithmConfig =
+            serde_json::from_str(r#"{"name": "FSRS", "cap": 365}"#).unwrap();
+        assert_eq!(
+            config,
+            AlgorithmConfig::Fsrs {
+                cap: 365,
+                desired_retention: 0.9,
+                weights: DEFAULT_WEIGHTS
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_algorithm() {
+        let config = serde_json::from_str::<AlgorithmConfig>(r#"{"name": "Leitner", "cap": 1}"#);
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn by_name() {
+        let config = AlgorithmConfig::Fsrs {
+            cap: 30,
+            desired_retention: 0.8,
+            weights: DEFAULT_WEIGHTS,
+        };
+        assert_eq!(config.with_name("fsrs"), Some(config.clone()));
+        assert_eq!(
+            config.with_name("SuperMemo 2"),
+            Some(AlgorithmConfig::SuperMemo2 { cap: 30 })
+        );
+        assert_eq!(config.with_name("Leitner"), None);
+        for name in ALGORITHM_NAMES {
+            assert_eq!(config.with_name(name).unwrap().name(), name);
+        }
+    }
+
+    #[test]
+    fn fsrs_weights() {
+        let config = AlgorithmConfig::Fsrs {
+            cap: 30,
+            desired_retention: 0.8,
+            weights: DEFAULT_WEIGHTS,
+        };
+        let weights = [1.0; 17];
+        assert_eq!(
+            config.with_fsrs_weights(weights).unwrap(),
+            AlgorithmConfig::Fsrs {
+                cap: 30,
+                desired_retention: 0.8,
+                weights
+            }
+        );
+        assert!(AlgorithmConfig::default()
+            .with_fsrs_weights(weights)
+            .is_err());
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        assert!(AlgorithmConfig::SuperMemo2 { cap: 0 }.build().is_err());
+        assert!(AlgorithmConfig::NaiveExponential { start: 0, cap: 10 }
+            .build()
+            .is_err());
+        assert!(AlgorithmConfig::Fsrs {
+            cap: 10,
+            desired_retention: 1.5,
+            weights: DEFAULT_WEIGHTS
+        }
+        .build()
+        .is_err());
+    }
+}
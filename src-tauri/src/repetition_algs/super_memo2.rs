@@ -0,0 +1,157 @@
+use log::debug;
+
+use crate::models::answer::Answer;
+use crate::repetition_algs::RepetitionAlgorithm;
+
+/// The SM-2 (SuperMemo 2) spaced repetition algorithm.
+/// Every answer adjusts the ease factor of the card based on how well the
+/// question was answered. The interval between repetitions then grows by
+/// this factor, so cards answered "barely right" are repeated sooner than
+/// cards answered perfectly.
+/// See <https://super-memory.com/english/ol/sm2.htm>.
+pub struct SuperMemo2RA {
+    /// The minimum quality (on the 0-5 SM-2 scale) which is considered
+    /// a correct answer. The original algorithm uses 3.
+    minimum_quality: i32,
+    /// A maximum day limit which overrides the computed interval.
+    limit: i32,
+}
+
+const INITIAL_EASE_FACTOR: f64 = 2.5;
+const MINIMUM_EASE_FACTOR: f64 = 1.3;
+
+impl SuperMemo2RA {
+    pub fn new(minimum_quality: i32, limit: i32) -> Self {
+        Self {
+            minimum_quality,
+            limit,
+        }
+    }
+
+    /// Maps the 0-100 answer rating onto the 0-5 SM-2 quality scale.
+    fn quality(answer: &Answer) -> i32 {
+        (answer.answer_rating as f64 / 20.0).round() as i32
+    }
+
+    /// Replays the whole answer history and returns the number of days
+    /// that should pass after the last answer before the question is repeated.
+    pub fn interval(&self, answers: &[Answer]) -> i32 {
+        let mut ease_factor = INITIAL_EASE_FACTOR;
+        let mut repetitions = 0;
+        let mut interval = 0;
+        for answer in answers {
+            let quality = Self::quality(answer);
+            if quality >= self.minimum_quality {
+                interval = match repetitions {
+                    0 => 1,
+                    1 => 6,
+                    _ => (interval as f64 * ease_factor).round() as i32,
+                };
+                repetitions += 1;
+            } else {
+                repetitions = 0;
+                interval = 1;
+            }
+            let q = (5 - quality) as f64;
+            ease_factor = (ease_factor + (0.1 - q * (0.08 + q * 0.02))).max(MINIMUM_EASE_FACTOR);
+        }
+        interval.min(self.limit)
+    }
+}
+
+impl RepetitionAlgorithm for SuperMemo2RA {
+    fn repeat_question(&self, answers: &[Answer]) -> bool {
+        match answers.last() {
+            None => true,
+            Some(last) => {
+                if Self::quality(last) < self.minimum_quality {
+                    return true;
+                }
+                let interval = self.interval(answers);
+                let now = chrono::Utc::now().naive_utc();
+                let days_between = now.signed_duration_since(last.timestamp).num_days();
+                debug!("days_between >= interval: {} >= {}", days_between, interval);
+                days_between >= interval as i64
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(rating: i32, days_ago: u64) -> Answer {
+        Answer {
+            id: None,
+            flashcard_id: 1,
+            answer_rating: rating,
+            timestamp: chrono::Utc::now()
+                .naive_utc()
+                .checked_sub_days(chrono::Days::new(days_ago))
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn no_questions() {
+        let sm2 = SuperMemo2RA::new(3, 365);
+        assert!(sm2.repeat_question(&[]));
+    }
+
+    #[test]
+    fn first_intervals() {
+        let sm2 = SuperMemo2RA::new(3, 365);
+        assert_eq!(sm2.interval(&[answer(100, 0)]), 1);
+        assert_eq!(sm2.interval(&[answer(100, 0), answer(100, 0)]), 6);
+        // Ease factor is 2.5 + 0.1 + 0.1 after two perfect answers.
+        assert_eq!(
+            sm2.interval(&[answer(100, 0), answer(100, 0), answer(100, 0)]),
+            16
+        );
+    }
+
+    #[test]
+    fn rating_granularity_matters() {
+        let sm2 = SuperMemo2RA::new(3, 365);
+        let perfect = [answer(100, 0), answer(100, 0), answer(100, 0)];
+        let barely = [answer(60, 0), answer(60, 0), answer(60, 0)];
+        assert!(sm2.interval(&barely) < sm2.interval(&perfect));
+    }
+
+    #[test]
+    fn wrong_answer_resets() {
+        let sm2 = SuperMemo2RA::new(3, 365);
+        let answers = [answer(100, 0), answer(100, 0), answer(0, 0), answer(100, 0)];
+        assert_eq!(sm2.interval(&answers), 1);
+    }
+
+    #[test]
+    fn last_incorrect() {
+        let sm2 = SuperMemo2RA::new(3, 365);
+        let answers = [answer(100, 3), answer(100, 2), answer(0, 0)];
+        assert!(sm2.repeat_question(&answers));
+    }
+
+    #[test]
+    fn dont_repeat_question() {
+        let sm2 = SuperMemo2RA::new(3, 365);
+        let answers = [answer(100, 10), answer(100, 9), answer(100, 3)];
+        assert!(!sm2.repeat_question(&answers));
+    }
+
+    #[test]
+    fn repeat_question() {
+        let sm2 = SuperMemo2RA::new(3, 365);
+        let answers = [answer(100, 10), answer(100, 7)];
+        assert!(sm2.repeat_question(&answers));
+    }
+
+    #[test]
+    fn interval_over_limit() {
+        let sm2 = SuperMemo2RA::new(3, 4);
+        let answers = [answer(100, 20), answer(100, 15), answer(100, 4)];
+        assert_eq!(sm2.interval(&answers), 4);
+        assert!(sm2.repeat_question(&answers));
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{bail, Result};
+use log::warn;
+use rand::seq::SliceRandom;
+
+use crate::db::{CardFilter, Db};
+use crate::models::flashcard::DECK_SEPARATOR;
+use crate::models::prelude::{Answer, Deck, Flashcard};
+use crate::repetition_algs::prelude::{AlgorithmConfig, RepetitionAlgorithm};
+
+/// The algorithms of the cards, built when first needed. Cards use the
+/// configured algorithm, unless their file selects another one. Cards with
+/// an unknown algorithm, for example synced by an older version, use the
+/// configured one too.
+struct Algorithms<'a> {
+    config: &'a AlgorithmConfig,
+    built: HashMap<Option<String>, Box<dyn RepetitionAlgorithm + Send + Sync>>,
+}
+
+impl<'a> Algorithms<'a> {
+    fn new(config: &'a AlgorithmConfig) -> Self {
+        Self {
+            config,
+            built: HashMap::new(),
+        }
+    }
+
+    fn for_card(&mut self, card: &Flashcard) -> Result<&dyn RepetitionAlgorithm> {
+        if !self.built.contains_key(&card.algorithm) {
+            let config = match &card.algorithm {
+                Some(name) => self.config.with_name(name).unwrap_or_else(|| {
+                    warn!("Unknown algorithm '{}', using the configured one", name);
+                    self.config.clone()
+                }),
+                None => self.config.clone(),
+            };
+            self.built.insert(card.algorithm.clone(), config.build()?);
+        }
+        Ok(self.built[&card.algorithm].as_ref())
+    }
+}
+
+/// Keeps only the first card of every note, so that siblings like a card
+/// and its reverse are not reviewed in the same session. The other siblings
+/// are left for the next session.
+fn without_siblings(cards: Vec<Flashcard>) -> Vec<Flashcard> {
+    let mut notes = HashSet::new();
+    cards
+        .into_iter()
+        .filter(|card| match (&card.path, card.note_line) {
+            (Some(path), Some(line)) => notes.insert((path.clone(), line)),
+            _ => true,
+        })
+        .collect()
+}
+
+/// Returns the cards matching the filter which should be reviewed according
+/// to the algorithm.
+pub async fn cards_to_review(
+    db: &Db,
+    algorithm: &AlgorithmConfig,
+    shuffle: bool,
+    filter: &CardFilter,
+) -> Result<Vec<Flashcard>> {
+    let cards = db.get_cards(filter).await?;
+    let mut algorithms = Algorithms::new(algorithm);
+    let mut result = vec![];
+    // We would like filter but async closures and such...
+    for card in cards {
+        let answers = db.get_answers(&card).await?;
+        if algorithms.for_card(&card)?.repeat_question(&answers) {
+            result.push(card);
+        }
+    }
+    if shuffle {
+        result.shuffle(&mut rand::thread_rng());
+    }
+    Ok(without_siblings(result))
+}
+
+/// Returns all decks with the child decks right after their parent,
+/// including the parent decks without their own cards. The counts of
+/// a deck include its child decks.
+pub async fn decks(db: &Db, algorithm: &AlgorithmConfig) -> Result<Vec<Deck>> {
+    let cards = db.get_cards(&CardFilter::default()).await?;
+    let mut algorithms = Algorithms::new(algorithm);
+    let mut answers: HashMap<i32, Vec<Answer>> = HashMap::new();
+    for answer in db.get_all_answers().await? {
+        answers.entry(answer.flashcard_id).or_default().push(answer);
+    }
+
+    // Sorted by the names of the parts, so that `a::b` comes before `a-b`.
+    let mut decks: BTreeMap<Vec<String>, Deck> = BTreeMap::new();
+    for card in cards {
+        let Some(deck) = &card.deck else {
+            continue;
+        };
+        let answers = card
+            .id
+            .and_then(|id| answers.get(&id))
+            .map_or(&[][..], |answers| answers.as_slice());
+        let due = algorithms.for_card(&card)?.repeat_question(answers);
+        let parts: Vec<String> = deck.split(DECK_SEPARATOR).map(String::from).collect();
+        for depth in 1..=parts.len() {
+            let deck = decks
+                .entry(parts[..depth].to_vec())
+                .or_insert_with(|| Deck {
+                    name: parts[..depth].join(DECK_SEPARATOR),
+                    ..Default::default()
+                });
+            deck.cards += 1;
+            deck.due += due as usize;
+            deck.new += answers.is_empty() as usize;
+        }
+    }
+    Ok(decks.into_values().collect())
+}
+
+/// Records an answer to the flashcard with the given rating.
+pub async fn answer_card(db: &Db, flashcard_id: i32, answer_rating: i32) -> Result<()> {
+    if !(0..=100).contains(&answer_rating) {
+        bail!("Answer rating must be between 0 and 100");
+    }
+    let answer = Answer {
+        id: None,
+        flashcard_id,
+        timestamp: chrono::Local::now().naive_local(),
+        answer_rating,
+    };
+    db.persist_answer(answer).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn siblings() {
+        let card = |id: i32, path: &str, note_line: Option<i64>| Flashcard {
+            id: Some(id),
+            path: Some(path.to_string()),
+            note_line,
+            ..Default::default()
+        };
+        let cards = vec![
+            card(1, "a.md", Some(1)),
+            card(2, "a.md", Some(1)),
+            card(3, "a.md", Some(5)),
+            card(4, "b.md", Some(1)),
+            card(5, "b.md", None),
+            card(6, "b.md", None),
+        ];
+        let ids: Vec<i32> = without_siblings(cards)
+            .into_iter()
+            .filter_map(|card| card.id)
+            .collect();
+        assert_eq!(ids, vec![1, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn unknown_algorithm() {
+        let config = AlgorithmConfig::default();
+        let mut algorithms = Algorithms::new(&config);
+        let card = Flashcard {
+            algorithm: Some("Leitner".to_string()),
+            ..Default::default()
+        };
+        assert!(algorithms.for_card(&card).is_ok());
+        let card = Flashcard {
+            algorithm: Some("fsrs".to_string()),
+            ..Default::default()
+        };
+        assert!(algorithms.for_card(&card).is_ok());
+        assert_eq!(algorithms.built.len(), 2);
+    }
+}
@@ -1,53 +1,637 @@
-use crate::models::prelude::Flashcard;
+use crate::db::{Db, SyncChanges};
+use crate::models::prelude::{Flashcard, SyncedFile};
+use crate::parsers::diagnostic::Diagnostic;
+use crate::parsers::parser::{markdown_files, parse_file, PARSER_VERSION};
+use crate::parsers::writeback::{write_card_ids, WrittenIds};
+use anyhow::{Context, Result};
+use log::warn;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
 use strsim::levenshtein;
 
 #[derive(Debug, PartialEq)]
 pub enum CardType {
     New(Flashcard),
     Old(Flashcard),
+    /// An old card which does not correspond to any new card, its source
+    /// text was removed.
+    Removed(Flashcard),
 }
 
-/// Sync two collection of flashcards.
-/// If a new card has very similar question to an old card, it is considered
-/// the same card and the old card with its ID is returned. Otherwise, it is
-/// returned as a new card without any ID.
-pub async fn sync(old_flashcards: &[Flashcard], new_flashcards: Vec<Flashcard>) -> Vec<CardType> {
-    // This function has O(n^2 * string_distance_computation) complexity, which
-    // can be devastating. We could and should do something about it, first
-    // optimization would be to somehow use the files the flashcards came from
-    // or sort them by their texts.
-    new_flashcards
-        .into_iter()
-        .map(|new_card| {
-            let similar: Vec<&Flashcard> = old_flashcards
-                .iter()
-                .filter(|old_card| {
-                    let avg_question_len = (old_card.question.len() + new_card.question.len()) / 2;
-                    let qe_limit = (avg_question_len as f64 * 0.2).ceil() as usize;
+/// A new card which was similar to more than one old card, or whose similar
+/// old card was also similar to another new card.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AmbiguousMatch {
+    /// Question of the new card.
+    pub question: String,
+    /// IDs of the similar old cards and their distances, closest first.
+    pub candidates: Vec<(Option<i32>, usize)>,
+    /// ID of the old card which was matched, if any.
+    pub matched: Option<i32>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SyncReport {
+    pub cards: Vec<CardType>,
+    pub ambiguous: Vec<AmbiguousMatch>,
+    /// `(index in cards, distance)` of the cards which were matched by
+    /// a similar question.
+    pub similar: Vec<(usize, usize)>,
+}
+
+/// The maximum question distance for two cards to be considered the same,
+/// the lengths are in characters.
+fn distance_limit(old_len: usize, new_len: usize) -> usize {
+    let avg_question_len = (old_len + new_len) / 2;
+    (avg_question_len as f64 * 0.2).ceil() as usize
+}
+
+/// Question with collapsed whitespace and in lowercase, cards with the same
+/// normalized question are matched without computing any distance.
+fn normalize(question: &str) -> String {
+    question
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+type Trigram = [char; 3];
+
+fn trigrams(chars: &[char]) -> HashSet<Trigram> {
+    chars.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+}
+
+/// Question of a card prepared for the fuzzy matching.
+struct FuzzyQuestion {
+    len: usize,
+    trigrams: HashSet<Trigram>,
+}
 
-                    // The same card probably exists
-                    levenshtein(&old_card.question, &new_card.question) <= qe_limit
+impl FuzzyQuestion {
+    fn new(question: &str) -> Self {
+        let chars: Vec<char> = question.chars().collect();
+        Self {
+            len: chars.len(),
+            trigrams: trigrams(&chars),
+        }
+    }
+}
+
+/// Whether the two cards may be matched, cards with different explicit IDs
+/// are never the same.
+fn can_match(old_card: &Flashcard, new_card: &Flashcard) -> bool {
+    old_card.uid.is_none() || new_card.uid.is_none()
+}
+
+/// Matches the unmatched cards with equal keys, in the order of the new and
+/// then the old cards.
+fn match_by_key<K: Hash + Eq>(
+    old_flashcards: &[Flashcard],
+    new_flashcards: &[Flashcard],
+    old_match: &mut [Option<usize>],
+    new_match: &mut [Option<usize>],
+    key: impl Fn(&Flashcard) -> K,
+) {
+    let mut by_key: HashMap<K, Vec<usize>> = HashMap::new();
+    for (old_idx, old_card) in old_flashcards.iter().enumerate() {
+        if old_match[old_idx].is_none() {
+            by_key.entry(key(old_card)).or_default().push(old_idx);
+        }
+    }
+    for (new_idx, new_card) in new_flashcards.iter().enumerate() {
+        if new_match[new_idx].is_some() {
+            continue;
+        }
+        let Some(old_indices) = by_key.get(&key(new_card)) else {
+            continue;
+        };
+        let found = old_indices.iter().copied().find(|&old_idx| {
+            old_match[old_idx].is_none() && can_match(&old_flashcards[old_idx], new_card)
+        });
+        if let Some(old_idx) = found {
+            old_match[old_idx] = Some(new_idx);
+            new_match[new_idx] = Some(old_idx);
+        }
+    }
+}
+
+/// Returns all pairs of unmatched cards with similar questions as
+/// `(distance, new index, old index)`.
+///
+/// Only the old cards which can be within the distance limit are compared.
+/// A single edit changes at most three trigrams, so two questions within
+/// distance `k` share at least `trigrams - 3k` trigrams, and their lengths
+/// differ by at most `k`. The old cards are indexed by their trigrams, and
+/// the rarest trigrams of the new question are used to find the candidates
+/// of the possible lengths.
+/// Questions which are too short for this are looked up by their length.
+fn similar_pairs(
+    old_flashcards: &[Flashcard],
+    new_flashcards: &[Flashcard],
+    old_match: &[Option<usize>],
+    new_match: &[Option<usize>],
+) -> Vec<(usize, usize, usize)> {
+    let old_questions: Vec<Option<FuzzyQuestion>> = old_flashcards
+        .iter()
+        .zip(old_match)
+        .map(|(card, matched)| {
+            matched
+                .is_none()
+                .then(|| FuzzyQuestion::new(&card.question))
+        })
+        .collect();
+    let mut by_len: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (old_idx, question) in old_questions.iter().enumerate() {
+        if let Some(question) = question {
+            by_len.entry(question.len).or_default().push(old_idx);
+        }
+    }
+    // The cards of each trigram are sorted by the question length.
+    let mut by_trigram: HashMap<Trigram, Vec<usize>> = HashMap::new();
+    for &old_idx in by_len.values().flatten() {
+        let Some(question) = &old_questions[old_idx] else {
+            continue;
+        };
+        for trigram in &question.trigrams {
+            by_trigram.entry(*trigram).or_default().push(old_idx);
+        }
+    }
+    let old_len = |old_idx: usize| old_questions[old_idx].as_ref().map_or(0, |q| q.len);
+
+    let mut pairs = vec![];
+    // The last new card for which the old card was compared.
+    let mut seen = vec![usize::MAX; old_flashcards.len()];
+    for (new_idx, new_card) in new_flashcards.iter().enumerate() {
+        if new_match[new_idx].is_some() {
+            continue;
+        }
+        let new_question = FuzzyQuestion::new(&new_card.question);
+        let n = new_question.len;
+        // The range of old question lengths which may be within the limit.
+        let mut max_len = n;
+        while max_len + 1 - n <= distance_limit(max_len + 1, n) {
+            max_len += 1;
+        }
+        let mut min_len = n;
+        while min_len > 0 && n - (min_len - 1) <= distance_limit(min_len - 1, n) {
+            min_len -= 1;
+        }
+        let max_limit = distance_limit(max_len, n);
+
+        let shared_needed = new_question.trigrams.len() as isize - 3 * max_limit as isize;
+        let candidates: Vec<usize> = if shared_needed > 0 {
+            let mut rarest: Vec<&[usize]> = new_question
+                .trigrams
+                .iter()
+                .map(|trigram| {
+                    let old_indices = by_trigram.get(trigram).map_or(&[][..], |v| v);
+                    let start = old_indices.partition_point(|&i| old_len(i) < min_len);
+                    let end = old_indices.partition_point(|&i| old_len(i) <= max_len);
+                    &old_indices[start..end]
                 })
                 .collect();
-            println!("{:?}", similar);
-            match similar.len() {
-                // TODO: Take the closest.
-                0 => CardType::New(new_card),
-                _ => CardType::Old(Flashcard {
-                    id: similar[0].id,
-                    question: new_card.question,
-                    answer: new_card.answer,
-                    folder: new_card.folder,
-                    path: new_card.path,
-                }),
+            rarest.sort_by_key(|old_indices| old_indices.len());
+            // At least one of these is shared with every similar question.
+            let probed = new_question.trigrams.len() - shared_needed as usize + 1;
+            rarest.into_iter().take(probed).flatten().copied().collect()
+        } else {
+            by_len
+                .range(min_len..=max_len)
+                .flat_map(|(_, v)| v)
+                .copied()
+                .collect()
+        };
+
+        for old_idx in candidates {
+            if seen[old_idx] == new_idx {
+                continue;
+            }
+            seen[old_idx] = new_idx;
+            let old_card = &old_flashcards[old_idx];
+            let Some(old_question) = &old_questions[old_idx] else {
+                continue;
+            };
+            if !can_match(old_card, new_card) {
+                continue;
+            }
+            let limit = distance_limit(old_question.len, n);
+            if old_question.len.abs_diff(n) > limit {
+                continue;
             }
+            let shared = old_question
+                .trigrams
+                .intersection(&new_question.trigrams)
+                .count();
+            let trigram_count = old_question.trigrams.len().max(new_question.trigrams.len());
+            if shared + 3 * limit < trigram_count {
+                continue;
+            }
+            let distance = levenshtein(&old_card.question, &new_card.question);
+            // The same card probably exists
+            if distance <= limit {
+                pairs.push((distance, new_idx, old_idx));
+            }
+        }
+    }
+    pairs
+}
+
+/// Sync two collection of flashcards.
+/// Cards with an explicit ID are matched to the old card with the same ID.
+/// Then the cards with the same normalized question are matched, first the
+/// ones from the same file and then from any file.
+/// Otherwise, if a new card has very similar question to an old card, it is
+/// considered the same card and the old card with its ID is returned.
+/// Cards with different explicit IDs are never considered similar.
+/// The remaining new cards are returned as new cards without any ID.
+/// The old cards which were not matched by any new card are returned as
+/// removed after all other cards, so the old cards should be only the ones
+/// from the synced folder.
+/// Similar cards are matched one-to-one, the closest pairs first, preferring
+/// cards from the same file. Ties are resolved by the order of the new and
+/// then the old cards. The new cards with more than one candidate are
+/// reported as ambiguous.
+pub async fn sync(old_flashcards: &[Flashcard], new_flashcards: Vec<Flashcard>) -> SyncReport {
+    let mut old_match: Vec<Option<usize>> = vec![None; old_flashcards.len()];
+    let mut new_match: Vec<Option<usize>> = vec![None; new_flashcards.len()];
+
+    // Reversed, so that the first old card with the ID is kept.
+    let by_uid: HashMap<&str, usize> = old_flashcards
+        .iter()
+        .enumerate()
+        .rev()
+        .filter_map(|(idx, card)| card.uid.as_deref().map(|uid| (uid, idx)))
+        .collect();
+    for (new_idx, new_card) in new_flashcards.iter().enumerate() {
+        let Some(&old_idx) = new_card.uid.as_deref().and_then(|uid| by_uid.get(uid)) else {
+            continue;
+        };
+        // The same ID is used multiple times, the first card wins.
+        if old_match[old_idx].is_none() {
+            old_match[old_idx] = Some(new_idx);
+            new_match[new_idx] = Some(old_idx);
+        }
+    }
+
+    match_by_key(
+        old_flashcards,
+        &new_flashcards,
+        &mut old_match,
+        &mut new_match,
+        |card| (card.path.clone(), normalize(&card.question)),
+    );
+    match_by_key(
+        old_flashcards,
+        &new_flashcards,
+        &mut old_match,
+        &mut new_match,
+        |card| normalize(&card.question),
+    );
+
+    let mut pairs = similar_pairs(old_flashcards, &new_flashcards, &old_match, &new_match);
+    pairs.sort_unstable_by_key(|&(distance, new_idx, old_idx)| {
+        let other_file = old_flashcards[old_idx].path != new_flashcards[new_idx].path;
+        (distance, other_file, new_idx, old_idx)
+    });
+    let mut similar = vec![];
+    for &(distance, new_idx, old_idx) in &pairs {
+        if new_match[new_idx].is_none() && old_match[old_idx].is_none() {
+            new_match[new_idx] = Some(old_idx);
+            old_match[old_idx] = Some(new_idx);
+            similar.push((new_idx, distance));
+        }
+    }
+    similar.sort_unstable();
+
+    let mut candidates: Vec<Vec<(usize, usize)>> = vec![vec![]; new_flashcards.len()];
+    let mut candidate_count = vec![0; old_flashcards.len()];
+    for &(distance, new_idx, old_idx) in &pairs {
+        candidates[new_idx].push((old_idx, distance));
+        candidate_count[old_idx] += 1;
+    }
+    let ambiguous = candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.len() > 1 || c.iter().any(|(old_idx, _)| candidate_count[*old_idx] > 1))
+        .map(|(new_idx, c)| AmbiguousMatch {
+            question: new_flashcards[new_idx].question.clone(),
+            candidates: c
+                .iter()
+                .map(|(old_idx, distance)| (old_flashcards[*old_idx].id, *distance))
+                .collect(),
+            matched: new_match[new_idx].and_then(|old_idx| old_flashcards[old_idx].id),
         })
+        .collect();
+
+    let mut cards: Vec<CardType> = new_flashcards
+        .into_iter()
+        .zip(new_match)
+        .map(|(new_card, matched)| match matched {
+            None => CardType::New(new_card),
+            Some(old_idx) => CardType::Old(Flashcard {
+                id: old_flashcards[old_idx].id,
+                ..new_card
+            }),
+        })
+        .collect();
+    cards.extend(
+        old_flashcards
+            .iter()
+            .zip(old_match)
+            .filter(|(_, matched)| matched.is_none())
+            .map(|(old_card, _)| CardType::Removed(old_card.clone())),
+    );
+    SyncReport {
+        cards,
+        ambiguous,
+        similar,
+    }
+}
+
+/// Number of cards changed by a folder synchronization.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub ambiguous: Vec<AmbiguousMatch>,
+    /// Number of files which were not parsed, because they did not change.
+    pub unchanged_files: usize,
+    /// Files which could not be parsed, their cards were kept as they were.
+    pub skipped_files: Vec<Diagnostic>,
+}
+
+/// FNV-1a hash of the file content. It has to be stable between runs,
+/// so the std hasher cannot be used.
+fn content_hash(content: &str) -> String {
+    let hash = content.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+async fn modified_time(path: &Path) -> Result<i64> {
+    let modified = tokio::fs::metadata(path)
+        .await
+        .with_context(|| format!("Failed to read metadata: {}", path.display()))?
+        .modified()?;
+    Ok(modified
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as i64))
+}
+
+/// Matches the new cards of the report with the archived cards, so that
+/// a card whose text was removed and later added back keeps its answers.
+/// The matched archived cards are restored, the others stay archived.
+/// Returns the IDs of the restored cards.
+async fn restore_archived(report: &mut SyncReport, archived: &[Flashcard]) -> HashSet<i32> {
+    let (positions, new_cards): (Vec<usize>, Vec<Flashcard>) = report
+        .cards
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, card)| match card {
+            CardType::New(card) => Some((idx, card.clone())),
+            _ => None,
+        })
+        .unzip();
+    if new_cards.is_empty() || archived.is_empty() {
+        return HashSet::new();
+    }
+    let restored = sync(archived, new_cards).await;
+    let mut ids = HashSet::new();
+    // The unmatched archived cards come last and are left out.
+    for (&idx, card) in positions.iter().zip(restored.cards) {
+        if let CardType::Old(card) = &card {
+            ids.extend(card.id);
+        }
+        report.cards[idx] = card;
+    }
+    report.similar.extend(
+        restored
+            .similar
+            .into_iter()
+            .map(|(new_idx, distance)| (positions[new_idx], distance)),
+    );
+    report.similar.sort_unstable();
+    report.ambiguous.extend(restored.ambiguous);
+    ids
+}
+
+/// The old cards which have to be reconciled with the parsed ones, all
+/// except the ones from the kept files.
+fn changed_cards(old_cards: Vec<Flashcard>, kept: &HashSet<String>) -> Vec<Flashcard> {
+    old_cards
+        .into_iter()
+        .filter(|card| !card.path.as_ref().is_some_and(|p| kept.contains(p)))
         .collect()
 }
 
+enum FileState {
+    /// Same modification time as during the last sync.
+    Unchanged,
+    /// A different modification time, but the same content.
+    Touched(SyncedFile),
+    Changed(SyncedFile, Vec<Flashcard>),
+}
+
+/// Whether the parsed card differs from the stored one it was matched with,
+/// so that the sync has to update it.
+pub fn is_changed(old: &Flashcard, new: &Flashcard) -> bool {
+    old.question != new.question
+        || old.answer != new.answer
+        || old.path != new.path
+        || old.uid != new.uid
+        || old.tags != new.tags
+        || old.deck != new.deck
+        || old.note_line != new.note_line
+        || old.algorithm != new.algorithm
+}
+
+/// Reads the file unless it is unchanged since the last sync. Files read by
+/// an older parser are always parsed again.
+async fn read_file(folder: &str, path: &Path, known: Option<&SyncedFile>) -> Result<FileState> {
+    let path_str = path.to_string_lossy().to_string();
+    let known = known.filter(|file| file.parser_version == PARSER_VERSION);
+    let mtime = modified_time(path).await?;
+    if known.is_some_and(|file| file.mtime == mtime) {
+        return Ok(FileState::Unchanged);
+    }
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read: {}", path.display()))?;
+    let file = SyncedFile {
+        path: path_str.clone(),
+        folder: folder.to_string(),
+        mtime,
+        hash: content_hash(&content),
+        parser_version: PARSER_VERSION,
+    };
+    if known.is_some_and(|known| known.hash == file.hash) {
+        return Ok(FileState::Touched(file));
+    }
+    let cards = parse_file(folder, &path_str, &content).await?;
+    Ok(FileState::Changed(file, cards))
+}
+
+/// The changes a folder sync would make.
+pub struct FolderPlan {
+    pub report: SyncReport,
+    /// The old cards which were reconciled with the parsed ones, including
+    /// the archived ones.
+    pub old_cards: Vec<Flashcard>,
+    /// IDs of the archived cards which were matched and will be restored.
+    pub restored: HashSet<i32>,
+    /// The files which were read, with their new modification time and hash.
+    pub files: Vec<SyncedFile>,
+    /// Paths of the files which did not change.
+    pub unchanged: HashSet<String>,
+    /// Paths of the synced files which no longer exist.
+    pub deleted: Vec<String>,
+    /// The files which could not be read or parsed, their cards are left
+    /// as they were.
+    pub errors: Vec<Diagnostic>,
+}
+
+/// Parses the markdown files in the folder which changed since the last
+/// sync and matches their cards with the ones in the database, without
+/// changing anything.
+///
+/// A file is unchanged if its modification time or the hash of its content
+/// is the same as during the last sync, and it was read by the same version
+/// of the parser. Files which cannot be parsed are reported and skipped,
+/// their cards in the database are kept. Fails if the folder cannot be read.
+pub async fn plan_folder_sync(db: &Db, folder: &str) -> Result<FolderPlan> {
+    let synced_files: HashMap<String, SyncedFile> = db
+        .get_synced_files(folder)
+        .await?
+        .into_iter()
+        .map(|file| (file.path.clone(), file))
+        .collect();
+
+    let mut new_cards = vec![];
+    let mut unchanged = HashSet::new();
+    let mut files = vec![];
+    let mut errors = vec![];
+    for path in markdown_files(folder)? {
+        let path_str = path.to_string_lossy().to_string();
+        match read_file(folder, &path, synced_files.get(&path_str)).await {
+            Ok(FileState::Unchanged) => {
+                unchanged.insert(path_str);
+            }
+            Ok(FileState::Touched(file)) => {
+                unchanged.insert(path_str);
+                files.push(file);
+            }
+            Ok(FileState::Changed(file, cards)) => {
+                new_cards.extend(cards);
+                files.push(file);
+            }
+            Err(e) => {
+                warn!("Skipping {}: {:#}", path_str, e);
+                errors.push(Diagnostic::from_error(&path_str, e));
+            }
+        }
+    }
+    let read: HashSet<&str> = files
+        .iter()
+        .map(|file| file.path.as_str())
+        .chain(errors.iter().filter_map(|error| error.file.as_deref()))
+        .collect();
+    let deleted = synced_files
+        .into_keys()
+        .filter(|path| !unchanged.contains(path) && !read.contains(path.as_str()))
+        .collect();
+    let mut kept = unchanged.clone();
+    kept.extend(errors.iter().filter_map(|error| error.file.clone()));
+    let mut old_cards = changed_cards(db.get_folder_cards(folder, false).await?, &kept);
+    let mut report = sync(&old_cards, new_cards).await;
+    let archived = db.get_folder_cards(folder, true).await?;
+    let restored = restore_archived(&mut report, &archived).await;
+    old_cards.extend(archived);
+    Ok(FolderPlan {
+        report,
+        old_cards,
+        restored,
+        files,
+        unchanged,
+        deleted,
+        errors,
+    })
+}
+
+/// Synchronizes the cards from the markdown files in the folder with the
+/// database, see [`plan_folder_sync`]. Cards from the folder which no longer
+/// exist in the files are archived, and archived cards whose text is back
+/// are restored. If `write_ids` is set, an ID is first written into the
+/// files for every valid card which does not have one yet.
+/// All changes are made in a single transaction, so a failed sync leaves
+/// the database untouched.
+pub async fn sync_folder(db: &Db, folder: &str, write_ids: bool) -> Result<SyncSummary> {
+    let written = if write_ids {
+        write_card_ids(folder).await
+    } else {
+        WrittenIds::default()
+    };
+    let mut plan = plan_folder_sync(db, folder).await?;
+    // A file which cannot be read is reported only once.
+    for error in written.errors {
+        if !plan.errors.iter().any(|e| e.file == error.file) {
+            plan.errors.push(error);
+        }
+    }
+    let report = plan.report;
+    for ambiguous in &report.ambiguous {
+        warn!(
+            "Ambiguous match for '{}', candidates {:?}, matched {:?}",
+            ambiguous.question, ambiguous.candidates, ambiguous.matched
+        );
+    }
+    let mut changes = SyncChanges {
+        files: plan.files,
+        removed_files: plan.deleted,
+        ..Default::default()
+    };
+    let old_cards: HashMap<Option<i32>, &Flashcard> =
+        plan.old_cards.iter().map(|card| (card.id, card)).collect();
+    for card in report.cards {
+        match card {
+            CardType::New(card) => changes.added.push(card),
+            CardType::Old(card) => {
+                let restored = card.id.is_some_and(|id| plan.restored.contains(&id));
+                let changed = old_cards
+                    .get(&card.id)
+                    .is_none_or(|old| is_changed(old, &card));
+                if restored || changed {
+                    changes.updated.push(card);
+                }
+            }
+            CardType::Removed(card) => changes.archived.push(card),
+        }
+    }
+    db.apply_sync(&changes).await?;
+    let summary = SyncSummary {
+        added: changes.added.len(),
+        updated: changes.updated.len(),
+        removed: changes.archived.len(),
+        ambiguous: report.ambiguous,
+        unchanged_files: plan.unchanged.len(),
+        skipped_files: plan.errors,
+    };
+    Ok(summary)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::memory_db;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::path::PathBuf;
 
     // TODO: Use everywhere
     fn question(q: &str) -> Flashcard {
@@ -57,19 +641,24 @@ mod tests {
             answer: "a1".to_string(),
             folder: None,
             path: None,
+            uid: None,
+            tags: vec![],
+            deck: None,
+            note_line: None,
+            algorithm: None,
         }
     }
 
     #[tokio::test]
     async fn empty_sync() {
-        assert_eq!(sync(&vec![], vec![]).await, vec![]);
+        assert_eq!(sync(&[], vec![]).await.cards, vec![]);
     }
 
     #[tokio::test]
     async fn no_sync() {
         let old = vec![question("q1")];
         let new = vec![question("q1")];
-        let synced = sync(&old, new).await;
+        let synced = sync(&old, new).await.cards;
         assert_eq!(synced.len(), 1);
         assert_eq!(
             synced[0],
@@ -79,6 +668,11 @@ mod tests {
                 answer: "a1".to_string(),
                 folder: None,
                 path: None,
+                uid: None,
+                tags: vec![],
+                deck: None,
+                note_line: None,
+                algorithm: None,
             })
         );
     }
@@ -87,7 +681,7 @@ mod tests {
     async fn sync_one() {
         let old = vec![question("a very loong text that has one typo in it")];
         let new = vec![question("a very long text that has one typo in it")];
-        let synced = sync(&old, new).await;
+        let synced = sync(&old, new).await.cards;
         assert_eq!(synced.len(), 1);
         assert_eq!(
             synced[0],
@@ -97,6 +691,11 @@ mod tests {
                 answer: "a1".to_string(),
                 folder: None,
                 path: None,
+                uid: None,
+                tags: vec![],
+                deck: None,
+                note_line: None,
+                algorithm: None,
             })
         );
     }
@@ -105,8 +704,8 @@ mod tests {
     async fn add_and_remove() {
         let old = vec![question("a flashcard")];
         let new = vec![question("arghargh")];
-        let synced = sync(&old, new).await;
-        assert_eq!(synced.len(), 1);
+        let synced = sync(&old, new).await.cards;
+        assert_eq!(synced.len(), 2);
         assert_eq!(
             synced[0],
             CardType::New(Flashcard {
@@ -115,8 +714,14 @@ mod tests {
                 answer: "a1".to_string(),
                 folder: None,
                 path: None,
+                uid: None,
+                tags: vec![],
+                deck: None,
+                note_line: None,
+                algorithm: None,
             })
         );
+        assert_eq!(synced[1], CardType::Removed(question("a flashcard")));
     }
 
     #[tokio::test]
@@ -130,8 +735,8 @@ mod tests {
             question("text of question 2 is a bore to be honest."),
             question("Some fascinating text"),
         ];
-        let synced = sync(&old, new).await;
-        assert_eq!(synced.len(), 3);
+        let synced = sync(&old, new).await.cards;
+        assert_eq!(synced.len(), 4);
         assert_eq!(
             synced[0],
             CardType::New(Flashcard {
@@ -140,6 +745,11 @@ mod tests {
                 answer: "a1".to_string(),
                 folder: None,
                 path: None,
+                uid: None,
+                tags: vec![],
+                deck: None,
+                note_line: None,
+                algorithm: None,
             })
         );
         assert_eq!(
@@ -150,6 +760,11 @@ mod tests {
                 answer: "a1".to_string(),
                 folder: None,
                 path: None,
+                uid: None,
+                tags: vec![],
+                deck: None,
+                note_line: None,
+                algorithm: None,
             })
         );
         assert_eq!(
@@ -160,8 +775,17 @@ mod tests {
                 answer: "a1".to_string(),
                 folder: None,
                 path: None,
+                uid: None,
+                tags: vec![],
+                deck: None,
+                note_line: None,
+                algorithm: None,
             })
         );
+        assert_eq!(
+            synced[3],
+            CardType::Removed(question("text of question 1 is very interesting"))
+        );
     }
 
     #[tokio::test]
@@ -171,8 +795,514 @@ mod tests {
             question("baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"),
             question("abaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"),
         ];
-        let synced = sync(&old, new.to_vec()).await;
-        assert_eq!(synced.len(), 1);
+        let synced = sync(&old, new.to_vec()).await.cards;
+        assert_eq!(synced.len(), 2);
         assert!(matches!(synced[0], CardType::Old(_)));
+        assert!(matches!(synced[1], CardType::Removed(_)));
+    }
+
+    #[tokio::test]
+    async fn remove_all() {
+        let old = [question("q1"), question("q2")];
+        let synced = sync(&old, vec![]).await.cards;
+        assert_eq!(
+            synced,
+            vec![
+                CardType::Removed(question("q1")),
+                CardType::Removed(question("q2"))
+            ]
+        );
+    }
+
+    fn with_uid(card: Flashcard, id: Option<i32>, uid: &str) -> Flashcard {
+        Flashcard {
+            id,
+            uid: Some(uid.to_string()),
+            ..card
+        }
+    }
+
+    #[tokio::test]
+    async fn sync_by_uid() {
+        let old = [
+            with_uid(question("What is the capital of France?"), Some(1), "a1"),
+            with_uid(question("What is the capital of Spain?"), Some(2), "b2"),
+        ];
+        let new = vec![
+            with_uid(question("Spain's capital city is?"), None, "b2"),
+            with_uid(question("What is the capital of Spain?"), None, "c3"),
+        ];
+        let synced = sync(&old, new).await.cards;
+        assert_eq!(
+            synced,
+            vec![
+                CardType::Old(with_uid(
+                    question("Spain's capital city is?"),
+                    Some(2),
+                    "b2"
+                )),
+                CardType::New(with_uid(
+                    question("What is the capital of Spain?"),
+                    None,
+                    "c3"
+                )),
+                CardType::Removed(old[0].clone()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_uid_falls_back_to_similarity() {
+        let old = [Flashcard {
+            id: Some(1),
+            ..question("a question without id")
+        }];
+        let new = vec![with_uid(question("a question without id"), None, "a1")];
+        let synced = sync(&old, new).await.cards;
+        assert_eq!(
+            synced,
+            vec![CardType::Old(with_uid(
+                question("a question without id"),
+                Some(1),
+                "a1"
+            ))]
+        );
+    }
+
+    #[tokio::test]
+    async fn closest_wins() {
+        let old = [
+            Flashcard {
+                id: Some(1),
+                ..question("What is the type of map function?")
+            },
+            Flashcard {
+                id: Some(2),
+                ..question("What is the type of fmap function?")
+            },
+        ];
+        let new = vec![question("What is the type of fmap function")];
+        let report = sync(&old, new).await;
+        assert_eq!(
+            report.cards[0],
+            CardType::Old(Flashcard {
+                id: Some(2),
+                ..question("What is the type of fmap function")
+            })
+        );
+        assert_eq!(report.cards[1], CardType::Removed(old[0].clone()));
+        assert_eq!(report.similar, vec![(0, 1)]);
+        assert_eq!(
+            report.ambiguous,
+            vec![AmbiguousMatch {
+                question: "What is the type of fmap function".to_string(),
+                candidates: vec![(Some(2), 1), (Some(1), 2)],
+                matched: Some(2),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn one_to_one() {
+        let old = [Flashcard {
+            id: Some(1),
+            ..question("a question about something")
+        }];
+        let new = vec![
+            question("a question about somethings!"),
+            question("a question about somethings"),
+        ];
+        let report = sync(&old, new).await;
+        assert_eq!(
+            report.cards,
+            vec![
+                CardType::New(question("a question about somethings!")),
+                CardType::Old(Flashcard {
+                    id: Some(1),
+                    ..question("a question about somethings")
+                }),
+            ]
+        );
+        assert_eq!(report.ambiguous.len(), 2);
+        assert_eq!(report.ambiguous[0].matched, None);
+        assert_eq!(report.ambiguous[1].matched, Some(1));
+    }
+
+    #[tokio::test]
+    async fn ties_are_deterministic() {
+        let old = [
+            Flashcard {
+                id: Some(1),
+                ..question("baaaaaaaaaaaaaaaaaaaaa")
+            },
+            Flashcard {
+                id: Some(2),
+                ..question("abaaaaaaaaaaaaaaaaaaaa")
+            },
+        ];
+        let new = vec![
+            question("aaaaaaaaaaaaaaaaaaaaaa"),
+            question("aaaaaaaaaaaaaaaaaaaaaa"),
+        ];
+        let report = sync(&old, new).await;
+        let ids: Vec<Option<i32>> = report
+            .cards
+            .iter()
+            .map(|c| match c {
+                CardType::Old(card) => card.id,
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ids, vec![Some(1), Some(2)]);
+    }
+
+    #[tokio::test]
+    async fn no_ambiguity() {
+        let old = [question("q1"), question("a completely different one")];
+        let new = vec![question("q1"), question("a completely different one")];
+        let report = sync(&old, new).await;
+        assert!(report.ambiguous.is_empty());
+    }
+
+    fn in_file(card: Flashcard, path: &str) -> Flashcard {
+        Flashcard {
+            path: Some(path.to_string()),
+            ..card
+        }
+    }
+
+    #[tokio::test]
+    async fn exact_match_prefers_same_file() {
+        let old = [
+            Flashcard {
+                id: Some(1),
+                ..in_file(question("What is a monad?"), "a.md")
+            },
+            Flashcard {
+                id: Some(2),
+                ..in_file(question("What is a monad?"), "b.md")
+            },
+        ];
+        let new = vec![
+            in_file(question("what is  a Monad?"), "b.md"),
+            in_file(question("What is a monad?"), "c.md"),
+        ];
+        let report = sync(&old, new).await;
+        assert_eq!(
+            report.cards,
+            vec![
+                CardType::Old(Flashcard {
+                    id: Some(2),
+                    ..in_file(question("what is  a Monad?"), "b.md")
+                }),
+                CardType::Old(Flashcard {
+                    id: Some(1),
+                    ..in_file(question("What is a monad?"), "c.md")
+                }),
+            ]
+        );
+        assert!(report.ambiguous.is_empty());
+    }
+
+    #[tokio::test]
+    async fn similar_prefers_same_file() {
+        let old = [
+            Flashcard {
+                id: Some(1),
+                ..in_file(question("What is the type of bind?"), "a.md")
+            },
+            Flashcard {
+                id: Some(2),
+                ..in_file(question("What is the type of bind?"), "b.md")
+            },
+        ];
+        let new = vec![in_file(question("What is the type of bind"), "b.md")];
+        let report = sync(&old, new).await;
+        assert_eq!(
+            report.cards[0],
+            CardType::Old(Flashcard {
+                id: Some(2),
+                ..in_file(question("What is the type of bind"), "b.md")
+            })
+        );
+    }
+
+    fn random_question(rng: &mut impl Rng) -> String {
+        let len = rng.gen_range(1..12);
+        (0..len)
+            .map(|_| {
+                // Words are picked from a vocabulary of a few thousand words.
+                let mut word_rng = StdRng::seed_from_u64(rng.gen_range(0..5000));
+                let word_len = word_rng.gen_range(1..9);
+                (0..word_len)
+                    .map(|_| word_rng.gen_range(b'a'..=b'z') as char)
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn edit(question: &str, rng: &mut impl Rng) -> String {
+        let mut chars: Vec<char> = question.chars().collect();
+        for _ in 0..rng.gen_range(0..4) {
+            let idx = rng.gen_range(0..=chars.len());
+            match rng.gen_range(0..3) {
+                0 => chars.insert(idx, 'z'),
+                1 if idx < chars.len() => {
+                    chars.remove(idx);
+                }
+                _ if idx < chars.len() => chars[idx] = 'q',
+                _ => {}
+            }
+        }
+        chars.into_iter().collect()
+    }
+
+    #[test]
+    fn pruning_finds_all_similar() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let old: Vec<Flashcard> = (0..150)
+            .map(|_| question(&random_question(&mut rng)))
+            .collect();
+        let new: Vec<Flashcard> = old
+            .iter()
+            .map(|card| question(&edit(&card.question, &mut rng)))
+            .collect();
+        let old_match = vec![None; old.len()];
+        let new_match = vec![None; new.len()];
+        let mut pairs = similar_pairs(&old, &new, &old_match, &new_match);
+        pairs.sort_unstable();
+
+        let mut expected = vec![];
+        for (new_idx, new_card) in new.iter().enumerate() {
+            for (old_idx, old_card) in old.iter().enumerate() {
+                let distance = levenshtein(&old_card.question, &new_card.question);
+                let limit = distance_limit(
+                    old_card.question.chars().count(),
+                    new_card.question.chars().count(),
+                );
+                if distance <= limit {
+                    expected.push((distance, new_idx, old_idx));
+                }
+            }
+        }
+        expected.sort_unstable();
+        assert_eq!(pairs, expected);
+    }
+
+    /// Run with `cargo test --release -- --ignored sync_100k --nocapture`.
+    #[tokio::test]
+    #[ignore]
+    async fn sync_100k() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let count = 100_000;
+        let old: Vec<Flashcard> = (0..count)
+            .map(|i| Flashcard {
+                id: Some(i),
+                path: Some(format!("{}.md", i / 50)),
+                ..question(&format!("{} {}", random_question(&mut rng), i))
+            })
+            .collect();
+        // Every tenth card has a typo, every hundredth is moved to another file.
+        let new: Vec<Flashcard> = old
+            .iter()
+            .enumerate()
+            .map(|(i, card)| Flashcard {
+                id: None,
+                question: if i % 10 == 0 {
+                    format!("{}?", card.question)
+                } else {
+                    card.question.clone()
+                },
+                path: if i % 100 == 0 {
+                    Some("moved.md".to_string())
+                } else {
+                    card.path.clone()
+                },
+                ..card.clone()
+            })
+            .collect();
+
+        let start = std::time::Instant::now();
+        let report = sync(&old, new).await;
+        println!("synced {} cards in {:?}", count, start.elapsed());
+        let matched = report
+            .cards
+            .iter()
+            .filter(|card| matches!(card, CardType::Old(_)))
+            .count();
+        assert_eq!(matched, count as usize);
+    }
+
+    #[test]
+    fn stable_content_hash() {
+        assert_eq!(content_hash(""), "cbf29ce484222325");
+        assert_eq!(content_hash("a"), "af63dc4c8601ec8c");
+        assert_ne!(content_hash("q #flashcard"), content_hash("q #flashcard "));
+    }
+
+    #[test]
+    fn only_changed_cards() {
+        let old = vec![
+            in_file(question("q1"), "unchanged.md"),
+            in_file(question("q2"), "changed.md"),
+            in_file(question("q3"), "deleted.md"),
+            question("q4"),
+        ];
+        let unchanged = HashSet::from(["unchanged.md".to_string()]);
+        assert_eq!(changed_cards(old.clone(), &unchanged), old[1..].to_vec());
+    }
+
+    fn temp_folder(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("flashcards-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn older_parser_reads_again() {
+        let folder = temp_folder("parser-version");
+        let path = folder.join("a.md");
+        std::fs::write(&path, "q #flashcard\na\n").unwrap();
+        let folder = folder.to_string_lossy().to_string();
+        let FileState::Changed(mut file, cards) = read_file(&folder, &path, None).await.unwrap()
+        else {
+            panic!("The file was not read");
+        };
+        assert_eq!(cards.len(), 1);
+        assert_eq!(file.parser_version, PARSER_VERSION);
+        assert!(matches!(
+            read_file(&folder, &path, Some(&file)).await.unwrap(),
+            FileState::Unchanged
+        ));
+
+        file.parser_version = PARSER_VERSION - 1;
+        assert!(matches!(
+            read_file(&folder, &path, Some(&file)).await.unwrap(),
+            FileState::Changed(..)
+        ));
+    }
+
+    #[tokio::test]
+    async fn unchanged_cards_are_not_updated() {
+        let folder = temp_folder("unchanged");
+        let path = folder.join("a.md");
+        std::fs::write(&path, "q1 #flashcard\na1\n---\nq2 #flashcard\na2\n").unwrap();
+        let folder = folder.to_string_lossy().to_string();
+        let db = memory_db().await;
+        let summary = sync_folder(&db, &folder, false).await.unwrap();
+        assert_eq!(summary.added, 2);
+
+        std::fs::write(
+            &path,
+            "q1 #flashcard\na1\n---\nq2 #flashcard\nb2\n---\nq3 #flashcard\na3\n",
+        )
+        .unwrap();
+        let summary = sync_folder(&db, &folder, false).await.unwrap();
+        assert_eq!(summary.added, 1);
+        assert_eq!(summary.updated, 1);
+        assert_eq!(summary.removed, 0);
+    }
+
+    #[tokio::test]
+    async fn missing_folder_keeps_cards() {
+        let folder = temp_folder("missing");
+        std::fs::write(folder.join("a.md"), "q #flashcard\na\n").unwrap();
+        let folder_str = folder.to_string_lossy().to_string();
+        let db = memory_db().await;
+        let summary = sync_folder(&db, &folder_str, false).await.unwrap();
+        assert_eq!(summary.added, 1);
+
+        std::fs::remove_dir_all(&folder).unwrap();
+        assert!(sync_folder(&db, &folder_str, false).await.is_err());
+        let cards = db.get_folder_cards(&folder_str, false).await.unwrap();
+        assert_eq!(cards.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn restore_archived_cards() {
+        let old = vec![with_uid(question("q1"), Some(1), "a")];
+        let archived = vec![
+            Flashcard {
+                id: Some(2),
+                ..question("a removed question which was added back")
+            },
+            Flashcard {
+                id: Some(3),
+                ..question("q3")
+            },
+        ];
+        let new = vec![
+            question("a removed question which was added bak"),
+            with_uid(question("q1"), None, "a"),
+            question("a new question"),
+        ];
+        let mut report = sync(&old, new).await;
+        let restored = restore_archived(&mut report, &archived).await;
+        assert_eq!(restored, HashSet::from([2]));
+        assert_eq!(
+            report.cards,
+            vec![
+                CardType::Old(Flashcard {
+                    id: Some(2),
+                    ..question("a removed question which was added bak")
+                }),
+                CardType::Old(with_uid(question("q1"), Some(1), "a")),
+                CardType::New(question("a new question")),
+            ]
+        );
+        assert_eq!(report.similar, vec![(0, 1)]);
+    }
+
+    #[tokio::test]
+    async fn write_ids_skips_bad_files() {
+        let folder = temp_folder("write-ids");
+        std::fs::write(folder.join("good.md"), "q1 #flashcard\na1\n").unwrap();
+        std::fs::write(folder.join("bad.md"), b"q2 #flashcard\n\xff\n").unwrap();
+        std::fs::write(
+            folder.join("invalid.md"),
+            "q3 #flashcard\na3\n---\nq4 #flashcard\n",
+        )
+        .unwrap();
+        let folder = folder.to_string_lossy().to_string();
+        let db = memory_db().await;
+
+        let summary = sync_folder(&db, &folder, true).await.unwrap();
+        assert_eq!(summary.added, 1);
+        let mut skipped: Vec<String> = summary
+            .skipped_files
+            .iter()
+            .filter_map(|error| error.file.clone())
+            .collect();
+        skipped.sort();
+        assert_eq!(
+            skipped,
+            vec![
+                format!("{}/bad.md", folder),
+                format!("{}/invalid.md", folder)
+            ]
+        );
+
+        let good = std::fs::read_to_string(format!("{}/good.md", folder)).unwrap();
+        assert!(good.starts_with("q1 #flashcard ^"));
+        let invalid = std::fs::read_to_string(format!("{}/invalid.md", folder)).unwrap();
+        assert!(invalid.starts_with("q3 #flashcard ^"));
+        assert!(invalid.ends_with("q4 #flashcard\n"));
+    }
+
+    #[tokio::test]
+    async fn file_error_line() {
+        let err = parse_file(
+            "/notes",
+            "/notes/a.md",
+            "q #flashcard\na\n---\nq2 #flashcard\n",
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            Diagnostic::from_error("/notes/a.md", err),
+            Diagnostic::error(4, 4, "Card cannot have empty answer text").in_file("/notes/a.md")
+        );
     }
 }
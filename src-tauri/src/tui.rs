@@ -0,0 +1,226 @@
+use std::io::{self, Write};
+
+use anyhow::{bail, Result};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use crate::db::Db;
+use crate::models::prelude::Flashcard;
+use crate::review;
+
+/// The keys used to rate a card, the ratings are the same as the ones
+/// of the buttons in the GUI.
+const RATINGS: [(char, i32, &str); 4] = [
+    ('1', 0, "Worst"),
+    ('2', 33, "Bad"),
+    ('3', 66, "Decent"),
+    ('4', 100, "Good"),
+];
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    Reveal,
+    Rate(i32),
+    Skip,
+    Quit,
+    Nothing,
+}
+
+/// Switches the terminal to raw mode on an alternate screen and restores
+/// it when dropped, even if the review fails.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn action(key: KeyEvent, revealed: bool) -> Action {
+    if key.kind != KeyEventKind::Press {
+        return Action::Nothing;
+    }
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Action::Quit,
+        KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+        KeyCode::Char('s') => Action::Skip,
+        KeyCode::Char(' ') | KeyCode::Enter if !revealed => Action::Reveal,
+        KeyCode::Char(c) if revealed => RATINGS
+            .iter()
+            .find(|(key, _, _)| *key == c)
+            .map(|(_, rating, _)| Action::Rate(*rating))
+            .unwrap_or(Action::Nothing),
+        _ => Action::Nothing,
+    }
+}
+
+/// Splits the text into lines which fit into the given width.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = vec![];
+    for line in text.trim_end().lines() {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.is_empty() {
+            lines.push(String::new());
+        }
+        for chunk in chars.chunks(width) {
+            lines.push(chunk.iter().collect());
+        }
+    }
+    lines
+}
+
+fn progress_bar(done: usize, total: usize, width: usize) -> String {
+    let filled = (done * width).checked_div(total).unwrap_or(0);
+    format!("{}{}", "#".repeat(filled), "-".repeat(width - filled))
+}
+
+fn draw(
+    out: &mut impl Write,
+    card: &Flashcard,
+    index: usize,
+    total: usize,
+    revealed: bool,
+) -> Result<()> {
+    let (width, height) = terminal::size()?;
+    let (width, height) = (width as usize, height as usize);
+    queue!(out, Clear(ClearType::All), MoveTo(0, 0))?;
+    queue!(
+        out,
+        SetAttribute(Attribute::Bold),
+        Print(format!(
+            "Review [{}/{}] {}",
+            index + 1,
+            total,
+            progress_bar(index, total, 20)
+        )),
+        SetAttribute(Attribute::Reset)
+    )?;
+
+    let mut lines = wrap(&card.question, width);
+    if revealed {
+        lines.push("-".repeat(width.min(40)));
+        lines.extend(wrap(&card.answer, width));
+    }
+    // Leave space for the header and the key hints.
+    for (row, line) in lines.iter().take(height.saturating_sub(4)).enumerate() {
+        queue!(out, MoveTo(0, row as u16 + 2), Print(line))?;
+    }
+
+    let hints = if revealed {
+        RATINGS
+            .iter()
+            .map(|(key, _, text)| format!("{}: {}", key, text))
+            .chain(["s: skip".to_string(), "q: quit".to_string()])
+            .collect::<Vec<_>>()
+            .join("  ")
+    } else {
+        "space: show answer  s: skip  q: quit".to_string()
+    };
+    queue!(
+        out,
+        MoveTo(0, height.saturating_sub(1) as u16),
+        SetAttribute(Attribute::Dim),
+        Print(hints),
+        SetAttribute(Attribute::Reset)
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Waits for the next terminal event without blocking the async runtime.
+async fn read_event() -> Result<Event> {
+    Ok(tokio::task::spawn_blocking(event::read).await??)
+}
+
+/// Interactive review of the cards in the terminal. The question is shown
+/// first, the answer is revealed on keypress and then the card is rated
+/// with the number keys. Returns the number of answered cards.
+pub async fn review(db: &Db, cards: &[Flashcard]) -> Result<usize> {
+    let guard = TerminalGuard::enter()?;
+    let mut stdout = io::stdout();
+    let mut answered = 0;
+    'cards: for (index, card) in cards.iter().enumerate() {
+        let mut revealed = false;
+        loop {
+            draw(&mut stdout, card, index, cards.len(), revealed)?;
+            let Event::Key(key) = read_event().await? else {
+                continue;
+            };
+            match action(key, revealed) {
+                Action::Reveal => revealed = true,
+                Action::Rate(rating) => {
+                    let Some(id) = card.id else {
+                        bail!("The card is missing ID!");
+                    };
+                    review::answer_card(db, id, rating).await?;
+                    answered += 1;
+                    continue 'cards;
+                }
+                Action::Skip => continue 'cards,
+                Action::Quit => break 'cards,
+                Action::Nothing => {}
+            }
+        }
+    }
+    drop(guard);
+    Ok(answered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn reveal_before_rating() {
+        assert_eq!(action(key(KeyCode::Char('4')), false), Action::Nothing);
+        assert_eq!(action(key(KeyCode::Char(' ')), false), Action::Reveal);
+        assert_eq!(action(key(KeyCode::Enter), false), Action::Reveal);
+        assert_eq!(action(key(KeyCode::Char('4')), true), Action::Rate(100));
+        assert_eq!(action(key(KeyCode::Char('1')), true), Action::Rate(0));
+        assert_eq!(action(key(KeyCode::Char('9')), true), Action::Nothing);
+    }
+
+    #[test]
+    fn quit_and_skip() {
+        assert_eq!(action(key(KeyCode::Char('q')), true), Action::Quit);
+        assert_eq!(action(key(KeyCode::Esc), false), Action::Quit);
+        assert_eq!(
+            action(
+                KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL),
+                false
+            ),
+            Action::Quit
+        );
+        assert_eq!(action(key(KeyCode::Char('s')), false), Action::Skip);
+    }
+
+    #[test]
+    fn wrap_lines() {
+        assert_eq!(wrap("abcdef\n\ngh\n", 4), vec!["abcd", "ef", "", "gh"]);
+        assert_eq!(wrap("", 4), Vec::<String>::new());
+    }
+
+    #[test]
+    fn progress() {
+        assert_eq!(progress_bar(0, 4, 8), "--------");
+        assert_eq!(progress_bar(2, 4, 8), "####----");
+        assert_eq!(progress_bar(0, 0, 4), "----");
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use serde::Serialize;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::sync::SyncSummary;
+
+/// Name of the event emitted to the frontend after a folder was synced.
+pub const SYNC_EVENT: &str = "folder-synced";
+
+/// How long the folder has to be quiet before it is synced, so that saving
+/// a file or switching a git branch results in a single sync.
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Payload of the [`SYNC_EVENT`], either the summary or the error.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncEvent {
+    pub folder: String,
+    pub summary: Option<SyncSummary>,
+    pub error: Option<String>,
+}
+
+/// The watched folders as configured, with the folder paths resolved to
+/// absolute paths without symlinks. The changed paths may be reported in
+/// either form.
+type WatchedFolders = HashMap<String, PathBuf>;
+
+fn canonical_folder(folder: &str) -> PathBuf {
+    std::fs::canonicalize(folder).unwrap_or_else(|_| PathBuf::from(folder))
+}
+
+/// Returns the watched folder which contains the changed path.
+fn changed_folder<'a>(path: &Path, folders: &'a WatchedFolders) -> Option<&'a String> {
+    let is_markdown = path.extension().is_some_and(|ext| ext == "md");
+    // A removed or renamed directory is reported without the files in it.
+    let maybe_dir = path.extension().is_none();
+    if !is_markdown && !maybe_dir {
+        return None;
+    }
+    folders
+        .iter()
+        .filter(|(folder, canonical)| path.starts_with(folder) || path.starts_with(canonical))
+        // The innermost folder if the watched folders are nested.
+        .max_by_key(|(_, canonical)| canonical.as_os_str().len())
+        .map(|(folder, _)| folder)
+}
+
+fn send_changes(
+    result: DebounceEventResult,
+    folders: &Mutex<WatchedFolders>,
+    tx: &UnboundedSender<String>,
+) {
+    let events = match result {
+        Ok(events) => events,
+        Err(e) => {
+            warn!("Watching folders failed: {}", e);
+            return;
+        }
+    };
+    let folders = folders.lock().unwrap();
+    let changed: HashSet<&String> = events
+        .iter()
+        .filter_map(|event| changed_folder(&event.path, &folders))
+        .collect();
+    for folder in changed {
+        debug!("{} changed", folder);
+        // The receiver is gone only when the app is shutting down.
+        let _ = tx.send(folder.clone());
+    }
+}
+
+/// Watches the synced folders for changes of markdown files. The changed
+/// folders are sent to the receiver returned from [`FolderWatcher::new`].
+pub struct FolderWatcher {
+    debouncer: Mutex<Debouncer<RecommendedWatcher>>,
+    folders: Arc<Mutex<WatchedFolders>>,
+}
+
+impl FolderWatcher {
+    pub fn new() -> Result<(Self, UnboundedReceiver<String>)> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let folders = Arc::new(Mutex::new(HashMap::new()));
+        let handler_folders = folders.clone();
+        let debouncer = new_debouncer(DEBOUNCE_TIMEOUT, move |result| {
+            send_changes(result, &handler_folders, &tx)
+        })
+        .context("Failed to create the folder watcher")?;
+        let watcher = Self {
+            debouncer: Mutex::new(debouncer),
+            folders,
+        };
+        Ok((watcher, rx))
+    }
+
+    /// Watches exactly the given folders, the folders which are no longer
+    /// in the list are unwatched.
+    pub fn watch(&self, folders: &[String]) -> Result<()> {
+        let wanted: HashSet<String> = folders.iter().cloned().collect();
+        // The folders are not locked while (un)watching, the event handler
+        // may be waiting for them.
+        let watched = self.folders.lock().unwrap().clone();
+        let mut debouncer = self.debouncer.lock().unwrap();
+        for folder in watched.keys().filter(|folder| !wanted.contains(*folder)) {
+            debug!("unwatching {}", folder);
+            if let Err(e) = debouncer.watcher().unwatch(Path::new(folder)) {
+                warn!("Failed to unwatch {}: {}", folder, e);
+            }
+        }
+        let mut result = Ok(());
+        let mut now_watched: WatchedFolders = watched
+            .iter()
+            .filter(|(folder, _)| wanted.contains(*folder))
+            .map(|(folder, canonical)| (folder.clone(), canonical.clone()))
+            .collect();
+        for folder in wanted
+            .iter()
+            .filter(|folder| !watched.contains_key(*folder))
+        {
+            debug!("watching {}", folder);
+            match debouncer
+                .watcher()
+                .watch(Path::new(folder), RecursiveMode::Recursive)
+                .with_context(|| format!("Failed to watch: {}", folder))
+            {
+                Ok(()) => {
+                    now_watched.insert(folder.clone(), canonical_folder(folder));
+                }
+                Err(e) => result = Err(e),
+            }
+        }
+        *self.folders.lock().unwrap() = now_watched;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_folders() {
+        let folders = WatchedFolders::from([
+            ("/notes".to_string(), PathBuf::from("/notes")),
+            ("/notes/lang".to_string(), PathBuf::from("/notes/lang")),
+        ]);
+        assert_eq!(
+            changed_folder(Path::new("/notes/a.md"), &folders).map(|f| f.as_str()),
+            Some("/notes")
+        );
+        assert_eq!(
+            changed_folder(Path::new("/notes/lang/haskell.md"), &folders).map(|f| f.as_str()),
+            Some("/notes/lang")
+        );
+        assert_eq!(
+            changed_folder(Path::new("/notes/removed_dir"), &folders).map(|f| f.as_str()),
+            Some("/notes")
+        );
+        assert_eq!(
+            changed_folder(Path::new("/notes/image.png"), &folders),
+            None
+        );
+        assert_eq!(changed_folder(Path::new("/other/a.md"), &folders), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_folder() {
+        let dir = std::env::temp_dir().join(format!("flashcards-watch-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("notes")).unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(dir.join("notes"), &link).unwrap();
+        let link = link.to_string_lossy().to_string();
+        let folders = WatchedFolders::from([(link.clone(), canonical_folder(&link))]);
+
+        let real = canonical_folder(&link).join("a.md");
+        assert_eq!(changed_folder(&real, &folders), Some(&link));
+        let linked = Path::new(&link).join("a.md");
+        assert_eq!(changed_folder(&linked, &folders), Some(&link));
+    }
+}
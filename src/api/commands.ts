@@ -1,17 +1,33 @@
 import { invoke } from '@tauri-apps/api/tauri';
-import type { Card } from './types/card';
+import type { Card, CardFilter, Deck, Tag } from './types/card';
+import type { SyncPreview } from './types/preview';
+import type { SyncSummary } from './events';
+import type { Diagnostic } from './types/diagnostic';
 
 export const getCard = async (id: number): Promise<Card | null> => invoke('get_card', { id });
 
-export async function getCards(): Promise<Card[]> {
-    const cardsJson = (await invoke('get_all_cards')) as Card[];
+export async function getCards(filter: CardFilter = {}): Promise<Card[]> {
+    const cardsJson = (await invoke('get_all_cards', { ...filter })) as Card[];
     return cardsJson;
 }
 
-export const getCardsToReview = async (shuffle: boolean) =>
-    invoke('get_cards_to_review', { shuffle }) as Promise<Card[]>;
+export const getCardsToReview = async (shuffle: boolean, filter: CardFilter = {}) =>
+    invoke('get_cards_to_review', { shuffle, ...filter }) as Promise<Card[]>;
+
+export const getTags = async () => invoke('get_tags') as Promise<Tag[]>;
+
+export const getDecks = async () => invoke('get_decks') as Promise<Deck[]>;
 
 export const saveAnswer = async (flashcardId: number, answerRating: number) =>
     invoke('answer_question', { flashcardId, answerRating });
 
-export const syncFolder = async (folder: string) => invoke('sync_flashcards', { folder });
+export const syncFolder = async (folder: string) =>
+    invoke('sync_flashcards', { folder }) as Promise<SyncSummary>;
+
+export const previewSync = async (folder: string) =>
+    invoke('preview_sync', { folder }) as Promise<SyncPreview>;
+
+export const optimizeFsrs = async () => invoke('optimize_fsrs') as Promise<number[]>;
+
+export const lintFolder = async (folder: string) =>
+    invoke('lint_folder', { folder }) as Promise<Diagnostic[]>;
@@ -0,0 +1,20 @@
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+import type { Diagnostic } from './types/diagnostic';
+
+export interface SyncSummary {
+    added: number;
+    updated: number;
+    removed: number;
+    unchangedFiles: number;
+    skippedFiles: Diagnostic[];
+}
+
+export interface SyncEvent {
+    folder: string;
+    summary: SyncSummary | null;
+    error: string | null;
+}
+
+// Called whenever a watched folder was synced in the background.
+export const onFolderSynced = (callback: (event: SyncEvent) => void): Promise<UnlistenFn> =>
+    listen<SyncEvent>('folder-synced', (event) => callback(event.payload));
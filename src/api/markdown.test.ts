@@ -1,4 +1,4 @@
-import { escape, escapeLatex, unescapeLatex } from './markdown';
+import { escape, escapeLatex, markdownToHtml, unescapeLatex } from './markdown';
 
 it('escape', () => {
     const encode = (text: string) => '>' + text + '<';
@@ -40,3 +40,9 @@ describe('escapeLatex real cases', () => {
   How do we choose $>H_0<$ and $>H_A<$?`);
     });
 });
+
+it('markdownToHtml code blocks', () => {
+    const html = markdownToHtml('```rust\nfn f() {\n    g();\n}\n```\n');
+    expect(html).toContain('class="rust language-rust"');
+    expect(html).toContain('fn f() {\n    g();\n}');
+});
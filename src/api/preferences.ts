@@ -1,59 +1,33 @@
+import { invoke } from '@tauri-apps/api/tauri';
 import type { config } from './types/config';
-import { appConfigDir } from '@tauri-apps/api/path';
-import { createDir, BaseDirectory, writeTextFile, readTextFile, exists } from '@tauri-apps/api/fs';
 
-const CONFIG_FILE = 'config.json';
+/// Older versions stored the config in the local storage under this key.
+const LEGACY_CONFIG_KEY = 'config';
 
-const DEFAULT_CONFIG: config = {
-    algorithm: {
-        name: 'Naive Exponential',
-        description: 'Repeats questions every x days.',
-        start: 2,
-        cap: 128
-    },
-    syncedFolders: [],
-    syncOnStartup: false,
-    randomShuffle: false
-};
-
-/// Tries to fetch config from local file, if not exists
-/// then a default config is created and saved to file.
-/// Not used for now.
-// eslint-disable-next-line @typescript-eslint/no-unused-vars
-const getConfigFromFile = async (): Promise<config> => {
-    const dir = await appConfigDir();
-    await createDir(dir, {
-        recursive: true
-    });
-
-    if (!(await exists(CONFIG_FILE, { dir: BaseDirectory.AppConfig }))) {
-        await writeTextFile(CONFIG_FILE, JSON.stringify(DEFAULT_CONFIG), {
-            dir: BaseDirectory.AppConfig
-        });
-    }
-
-    const config = await readTextFile(CONFIG_FILE, {
-        dir: BaseDirectory.AppConfig
-    });
-
-    return JSON.parse(config);
-};
-
-export const getConfig = (): config => {
-    const config = localStorage.getItem('config');
-    if (!config) {
-        localStorage.setItem('config', JSON.stringify(DEFAULT_CONFIG));
-        return DEFAULT_CONFIG;
+/// Moves the config from the local storage of older versions to the backend.
+const migrateLegacyConfig = async () => {
+    const legacy = localStorage.getItem(LEGACY_CONFIG_KEY);
+    if (legacy === null) {
+        return;
     }
     try {
-        const result = JSON.parse(config);
-        return result;
+        await invoke('migrate_config', { config: JSON.parse(legacy) });
+        localStorage.removeItem(LEGACY_CONFIG_KEY);
     } catch (e) {
-        localStorage.setItem('config', JSON.stringify(DEFAULT_CONFIG));
-        return DEFAULT_CONFIG;
+        console.warn(`Failed to migrate the config: ${e}`);
     }
 };
 
-export const saveConfig = async (config: config) => {
-    localStorage.setItem('config', JSON.stringify(config));
+/// The config is stored by the backend in a file next to the database,
+/// so that the command line interface uses the same settings.
+export const getConfig = async (): Promise<config> => {
+    await migrateLegacyConfig();
+    return invoke('get_config') as Promise<config>;
 };
+
+/// Returns why the config file could not be loaded, in which case the
+/// default config is used.
+export const getConfigError = async (): Promise<string | null> =>
+    invoke('get_config_error') as Promise<string | null>;
+
+export const saveConfig = async (config: config) => invoke('set_config', { config });
@@ -1,11 +1,15 @@
-enum AlgorithmType {
-    NaiveExponential = 'Naive Exponential'
+export enum AlgorithmType {
+    NaiveExponential = 'Naive Exponential',
+    SuperMemo2 = 'SuperMemo 2',
+    Fsrs = 'FSRS'
 }
 
 // Do it this way so that we can iterate over them.
 interface AlgorithmBase {
     name: AlgorithmType;
     description: string;
+    // The maximum number of days to wait before repeating a question.
+    cap: number;
 }
 
 export interface NaiveExponential extends AlgorithmBase {
@@ -13,9 +17,49 @@ export interface NaiveExponential extends AlgorithmBase {
     description: 'Repeats questions every power of 2 days. So, 2, 4, 8, 16, 32, etc., It is naive because it does take into account only the first x good answers.';
     // The least number of days to wait before repeating a question.
     start: number;
-    // The maximum number of days to wait before repeating a question.
-    cap: number;
 }
 
-export type Algorithm = NaiveExponential;
+export interface SuperMemo2 extends AlgorithmBase {
+    name: AlgorithmType.SuperMemo2;
+    description: 'The SM-2 algorithm. The better the answer, the longer the interval before the next repetition.';
+}
+
+export interface Fsrs extends AlgorithmBase {
+    name: AlgorithmType.Fsrs;
+    description: 'Free Spaced Repetition Scheduler. Models the memory of every card and repeats it before it is likely forgotten.';
+    // The probability of recall at which the card is repeated.
+    desiredRetention: number;
+    // Fitted model weights, the defaults are used if missing.
+    weights?: number[];
+}
+
+export type Algorithm = NaiveExponential | SuperMemo2 | Fsrs;
 export const algorithmTypes = Object.values(AlgorithmType);
+
+export const defaultAlgorithm = (name: AlgorithmType): Algorithm => {
+    switch (name) {
+        case AlgorithmType.NaiveExponential:
+            return {
+                name,
+                description:
+                    'Repeats questions every power of 2 days. So, 2, 4, 8, 16, 32, etc., It is naive because it does take into account only the first x good answers.',
+                start: 2,
+                cap: 128
+            };
+        case AlgorithmType.SuperMemo2:
+            return {
+                name,
+                description:
+                    'The SM-2 algorithm. The better the answer, the longer the interval before the next repetition.',
+                cap: 365
+            };
+        case AlgorithmType.Fsrs:
+            return {
+                name,
+                description:
+                    'Free Spaced Repetition Scheduler. Models the memory of every card and repeats it before it is likely forgotten.',
+                cap: 36500,
+                desiredRetention: 0.9
+            };
+    }
+};
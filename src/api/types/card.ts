@@ -4,4 +4,30 @@ export interface Card {
     answer: string;
     folder?: string;
     path?: string;
+    uid?: string;
+    tags: string[];
+    deck?: string;
+    // Cards generated from the same note, like a card and its reverse, share it.
+    note_line?: number;
+    // The repetition algorithm set by the front matter of the card's file.
+    algorithm?: string;
+}
+
+export interface Tag {
+    name: string;
+    cards: number;
+}
+
+export interface Deck {
+    name: string;
+    cards: number;
+    due: number;
+    new: number;
+}
+
+export interface CardFilter {
+    tags?: string[];
+    deck?: string;
+    // Whether the child decks of the deck are included, they are by default.
+    subdecks?: boolean;
 }
@@ -9,4 +9,6 @@ export interface config {
     syncOnStartup: boolean;
     /// Shuffle the cards before reviewing.
     randomShuffle: boolean;
+    /// Write IDs of the cards into the synced markdown files.
+    writeCardIds: boolean;
 }
@@ -0,0 +1,15 @@
+export type Severity = 'Error' | 'Warning';
+
+export interface Diagnostic {
+    file: string | null;
+    // Zero if the diagnostic is about the whole file.
+    line: number;
+    column: number;
+    severity: Severity;
+    message: string;
+}
+
+export const describeDiagnostic = (diagnostic: Diagnostic) => {
+    const location = diagnostic.line > 0 ? `:${diagnostic.line}:${diagnostic.column}` : '';
+    return `${diagnostic.file ?? ''}${location}: ${diagnostic.message}`;
+};
@@ -0,0 +1,24 @@
+import type { Card } from './card';
+import type { Diagnostic } from './diagnostic';
+
+export type DiffLine =
+    | { kind: 'Same'; line: string }
+    | { kind: 'Added'; line: string }
+    | { kind: 'Removed'; line: string };
+
+export interface CardChange {
+    old: Card;
+    new: Card;
+    distance: number | null;
+    questionDiff: DiffLine[];
+    answerDiff: DiffLine[];
+}
+
+export interface SyncPreview {
+    new: Card[];
+    updated: CardChange[];
+    removed: Card[];
+    unchanged: number;
+    unchangedFiles: number;
+    skippedFiles: Diagnostic[];
+}